@@ -1,24 +1,60 @@
 use {
-    crate::ActionsError,
+    crate::{
+        ActionsError,
+        budget::{self, Budget, Check},
+        clicks::{self, ClickKind, SurfaceKey},
+        controls::{AppProfile, Controls, Hotkeys, SessionSnapshot},
+        dashboard::Dashboard,
+        filter::{Filter, Input, Rule},
+        history::History,
+        keyboard::{self, KeyboardLayout},
+        metrics, remote, socket,
+        status_bar::{StatusBar, StatusBarFormat},
+    },
+    error_reporter::Report,
+    signal_hook::{
+        consts::{SIGUSR1, SIGUSR2},
+        iterator::Signals,
+    },
     std::{
         any::Any,
+        cell::RefCell,
         collections::HashSet,
+        fs::File,
+        io::{self, BufWriter, Write},
+        net::SocketAddr,
+        path::{Path, PathBuf},
         process::{Command, exit},
         rc::Rc,
         sync::{
-            Arc, Mutex,
-            atomic::{AtomicBool, AtomicU64, Ordering},
+            atomic::{AtomicU32, AtomicU64, Ordering},
+            mpsc,
         },
         thread,
         time::{Duration, Instant},
     },
+    wl_actions::{
+        Action, Event, Session, Source, detector,
+        privacy::Privacy,
+        repeats::RepeatInfo,
+        summary,
+        weights::{Weights, format_total},
+    },
     wl_proxy::{
         baseline::Baseline,
         fixed::Fixed,
         object::{Object, ObjectCoreApi},
         protocols::{
             ObjectInterface,
+            text_input_unstable_v3::{
+                zwp_text_input_manager_v3::{ZwpTextInputManagerV3, ZwpTextInputManagerV3Handler},
+                zwp_text_input_v3::{
+                    ZwpTextInputV3, ZwpTextInputV3ContentHint, ZwpTextInputV3ContentPurpose,
+                    ZwpTextInputV3Handler,
+                },
+            },
             wayland::{
+                wl_callback::WlCallback,
                 wl_display::{WlDisplay, WlDisplayHandler},
                 wl_keyboard::{WlKeyboard, WlKeyboardHandler, WlKeyboardKeyState},
                 wl_pointer::{WlPointer, WlPointerAxis, WlPointerButtonState, WlPointerHandler},
@@ -27,179 +63,358 @@ use {
                 wl_surface::WlSurface,
                 wl_touch::{WlTouch, WlTouchHandler},
             },
+            xdg_shell::{
+                xdg_surface::{XdgSurface, XdgSurfaceHandler},
+                xdg_toplevel::{XdgToplevel, XdgToplevelHandler},
+                xdg_wm_base::{XdgWmBase, XdgWmBaseHandler},
+            },
         },
         simple::{SimpleCommandExt, SimpleProxy},
     },
 };
 
-pub struct ActionCounters {
-    pub key_presses: AtomicU64,
-    pub button_clicks: AtomicU64,
-    pub scroll_steps: AtomicU64,
-    pub touch_taps: AtomicU64,
-}
-
-impl ActionCounters {
-    pub fn new() -> Self {
-        Self {
-            key_presses: AtomicU64::new(0),
-            button_clicks: AtomicU64::new(0),
-            scroll_steps: AtomicU64::new(0),
-            touch_taps: AtomicU64::new(0),
-        }
-    }
+/// How often the dashboard fetches the details while nothing changes.
+const DETAILS_EVERY: Duration = Duration::from_secs(1);
 
-    pub fn total(&self) -> u64 {
-        // Only count keys and clicks in total (scroll events are too granular)
-        self.key_presses.load(Ordering::Relaxed)
-            + self.button_clicks.load(Ordering::Relaxed)
-            + self.touch_taps.load(Ordering::Relaxed)
-    }
+/// Session settings, as given on the command line.
+pub struct Options {
+    pub quiet: bool,
+    /// Log the seats, pointers and input seen.
+    pub verbose: bool,
+    /// Show the full-screen dashboard instead of the live line.
+    pub dashboard: bool,
+    pub lap_names: Vec<String>,
+    pub hotkeys: Hotkeys,
+    /// Profiles applied once the application sets a matching app_id.
+    pub app_profiles: Vec<AppProfile>,
+    /// Rules for dropping, remapping and rate-limiting keys and buttons.
+    pub filter: Vec<Rule>,
+    /// Where to create the control socket, if anywhere.
+    pub control_socket: Option<PathBuf>,
+    pub status_bar: Option<StatusBarFormat>,
+    /// Where to write the status bar output instead of stdout.
+    pub status_bar_output: Option<PathBuf>,
+    /// Where to serve Prometheus metrics.
+    pub metrics_listen: Option<SocketAddr>,
+    /// Where to save the session when it ends.
+    pub history_db: Option<PathBuf>,
+    /// Where to write the summary as JSON when the session ends.
+    pub summary_json: Option<PathBuf>,
+    /// Where to record the event stream for `wl-actions replay`.
+    pub record: Option<PathBuf>,
+    /// Limits checked when the session ends.
+    pub budget: Budget,
+    pub scroll_debounce: Duration,
+    pub count_repeats: bool,
+    pub weights: Weights,
+    pub click_heatmap: Option<PathBuf>,
+    pub click_csv: Option<PathBuf>,
+    pub keyboard_heatmap: Option<PathBuf>,
+    pub keyboard_layout: KeyboardLayout,
+    pub privacy: Privacy,
 }
 
-pub fn main(quiet: bool, program: Vec<String>) -> Result<(), ActionsError> {
+pub fn main(options: Options, program: Vec<String>) -> Result<(), ActionsError> {
+    let quiet = options.quiet;
+    let live_line = !quiet && !options.dashboard;
+
     // Print version info
     let git_hash = option_env!("GIT_HASH").unwrap_or("unknown");
     if !quiet {
         eprintln!("wl-actions ({})", git_hash);
     }
 
+    let listener = match &options.control_socket {
+        Some(path) => Some(socket::bind(path).map_err(ActionsError::ControlSocket)?),
+        None => None,
+    };
+    if !quiet && let Some(path) = &options.control_socket {
+        eprintln!("Control socket: {}", path.display());
+    }
+    let metrics_listener = match options.metrics_listen {
+        Some(addr) => Some(metrics::bind(addr).map_err(ActionsError::MetricsListen)?),
+        None => None,
+    };
+    if !quiet && let Some(addr) = options.metrics_listen {
+        eprintln!("Metrics: http://{}/metrics", addr);
+    }
+
     let server = SimpleProxy::new(Baseline::ALL_OF_THEM).map_err(ActionsError::CreateServer)?;
-    let child = Command::new(&program[0])
+    let mut command = Command::new(&program[0]);
+    command
         .args(&program[1..])
-        .with_wayland_display(server.display())
-        .spawn()
-        .map_err(ActionsError::SpawnChild)?;
+        .with_wayland_display(server.display());
+    if let Some(path) = &options.control_socket {
+        command.env("WL_ACTIONS_SOCKET", path);
+    }
+    let recording = match &options.record {
+        Some(path) => {
+            Some(File::create(path).map_err(|e| ActionsError::CreateRecording(path.clone(), e))?)
+        }
+        None => None,
+    };
+    let child = command.spawn().map_err(ActionsError::SpawnChild)?;
+
+    let start = Instant::now();
+    let mut session = Session::new(start, options.lap_names);
+    session.program = program.clone();
+    session.scroll_debounce = options.scroll_debounce;
+    session.count_repeats = options.count_repeats;
+    session.set_privacy(options.privacy);
+    session.weights = options.weights;
+    for detector in detector::builtin() {
+        session.add_detector(detector);
+    }
+    if let Some(file) = recording {
+        session.record_to(Box::new(BufWriter::new(file)), start);
+    }
+    let mut controls = Controls::new(session, live_line);
+    controls.hotkeys.set(options.hotkeys);
+    *controls.app_profiles.borrow_mut() = options.app_profiles;
+    controls.verbose = options.verbose;
+    controls.filter = Rc::new(RefCell::new(Filter::new(options.filter)));
+    controls.socket_path = options.control_socket;
+    controls.history_db = options.history_db;
+    controls.summary_json = options.summary_json;
+    controls.recording = options.record;
+    *controls.budget.borrow_mut() = options.budget;
+    controls.click_heatmap = options.click_heatmap;
+    controls.click_csv = options.click_csv;
+    controls.keyboard_heatmap = options.keyboard_heatmap;
+    controls.keyboard_layout = options.keyboard_layout;
 
-    let counters = Arc::new(ActionCounters::new());
-    let running = Arc::new(AtomicBool::new(true));
-    let start_time = Instant::now();
+    // The session stays on this thread; the others queue jobs for it
+    let (remote, jobs) = remote::channel(server.display());
+    controls.jobs = Some(Rc::new(jobs));
 
     // Set up Ctrl+C handler - print summary and exit
     {
-        let counters = counters.clone();
-        let running = running.clone();
+        let remote = remote.clone();
         ctrlc::set_handler(move || {
-            running.store(false, Ordering::Relaxed);
-            // Clear the live output line
-            eprintln!();
-            print_summary(&counters, start_time);
-            exit(0);
+            remote.call(|controls| {
+                stop(controls);
+            });
         })
         .expect("Error setting Ctrl-C handler");
     }
 
-    // Spawn display thread if not quiet
-    if !quiet {
-        let counters_clone = counters.clone();
-        let running_clone = running.clone();
+    if let Some(listener) = listener {
+        socket::spawn(listener, remote.clone());
+    }
+    if let Some(listener) = metrics_listener {
+        metrics::spawn(
+            listener,
+            remote.clone(),
+            metrics::program_label(&program[0]),
+        );
+    }
+
+    // Record a lap marker on SIGUSR1, toggle pause on SIGUSR2
+    {
+        let remote = remote.clone();
+        let mut signals = Signals::new([SIGUSR1, SIGUSR2]).map_err(ActionsError::Signals)?;
         thread::spawn(move || {
-            while running_clone.load(Ordering::Relaxed) {
-                let keys = counters_clone.key_presses.load(Ordering::Relaxed);
-                let clicks = counters_clone.button_clicks.load(Ordering::Relaxed);
-                let scrolls = counters_clone.scroll_steps.load(Ordering::Relaxed);
-                let touch = counters_clone.touch_taps.load(Ordering::Relaxed);
-                let total = counters_clone.total();
-                eprint!(
-                    "\rKeys: {} | Clicks: {} | Scrolls: {} | Touch: {} | Total: {} (keys+clicks)    ",
-                    keys, clicks, scrolls, touch, total
-                );
+            for signal in signals.forever() {
+                match signal {
+                    SIGUSR1 => {
+                        remote.call(|controls| {
+                            controls.record_lap(None);
+                        });
+                    }
+                    SIGUSR2 => {
+                        remote.call(Controls::toggle_pause);
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    // Spawn display thread for the live output, the dashboard and the status
+    // bar, fed with snapshots from the proxy thread
+    let mut status_bar = options
+        .status_bar
+        .map(|format| StatusBar::new(format, options.status_bar_output));
+    if live_line || options.dashboard || status_bar.is_some() {
+        let (sender, snapshots) = mpsc::channel();
+        controls.snapshots = Some(sender);
+        controls.publish();
+        let running = controls.running.clone();
+        let remote = remote.clone();
+        let dashboard = options
+            .dashboard
+            .then(|| metrics::program_label(&program[0]));
+        let display = thread::spawn(move || {
+            let mut dashboard = dashboard.map(Dashboard::new);
+            let mut latest: Option<SessionSnapshot> = None;
+            let mut details_at: Option<Instant> = None;
+            while running.load(Ordering::Relaxed) {
+                if let Some(mut snapshot) = snapshots.try_iter().last() {
+                    // Keep showing the details until they are fetched again
+                    snapshot.details = latest.take().and_then(|latest| latest.details);
+                    latest = Some(snapshot);
+                    details_at = None;
+                }
+                if let Some(snapshot) = &mut latest {
+                    let counts = snapshot.counts;
+                    if live_line {
+                        let state = if snapshot.paused { "PAUSED | " } else { "" };
+                        eprint!(
+                            "\r{}Keys: {} | Clicks: {} | Scrolls: {} | Touch: {} | Total: {} ({})    ",
+                            state,
+                            counts.key_presses,
+                            counts.button_clicks,
+                            counts.scroll_steps,
+                            counts.touch_taps,
+                            format_total(snapshot.weights.total(&counts)),
+                            snapshot.weights
+                        );
+                    }
+                    if let Some(dashboard) = &mut dashboard {
+                        // The details take some work, so they are fetched
+                        // once per change, and every second for the lap times
+                        if details_at.is_none_or(|at| at.elapsed() >= DETAILS_EVERY) {
+                            if let Some(details) = remote.call(Controls::details) {
+                                snapshot.details = Some(details);
+                            }
+                            details_at = Some(Instant::now());
+                        }
+                        dashboard.update(snapshot);
+                    }
+                    if let Some(status_bar) = &mut status_bar {
+                        status_bar.update(snapshot);
+                    }
+                }
                 thread::sleep(Duration::from_millis(100));
             }
         });
+        *controls.display.borrow_mut() = Some(display);
     }
 
     // Run the proxy - this will block until the child exits or server errors
-    let counters_for_handler = counters.clone();
-    let pressed_keys = Arc::new(Mutex::new(HashSet::new()));
-    let pressed_buttons = Arc::new(Mutex::new(HashSet::new()));
-    let last_scroll_time = Arc::new(Mutex::new(Instant::now()));
-    let pressed_keys_for_handler = pressed_keys.clone();
-    let pressed_buttons_for_handler = pressed_buttons.clone();
-    let last_scroll_time_for_handler = last_scroll_time.clone();
+    let controls_for_handler = controls.clone();
     let err = server.run(move || WlDisplayHandlerImpl {
-        counters: counters_for_handler.clone(),
-        pressed_keys: pressed_keys_for_handler.clone(),
-        pressed_buttons: pressed_buttons_for_handler.clone(),
-        last_scroll_time: last_scroll_time_for_handler.clone(),
+        controls: controls_for_handler.clone(),
     });
 
-    running.store(false, Ordering::Relaxed);
+    controls.stop_display();
 
     // Wait for child to exit
     let _ = child.id(); // Just to ensure child is still valid
 
     // Clear the live output line
-    if !quiet {
+    if live_line {
         eprintln!();
     }
 
     // Print summary
-    print_summary(&counters, start_time);
+    if !finish(&controls) {
+        exit(budget::EXIT_BUDGET_EXCEEDED);
+    }
 
     Err(ActionsError::ServerFailed(err))
 }
 
-fn print_summary(counters: &ActionCounters, start_time: Instant) {
-    let duration = start_time.elapsed();
-    let keys = counters.key_presses.load(Ordering::Relaxed);
-    let clicks = counters.button_clicks.load(Ordering::Relaxed);
-    let scrolls = counters.scroll_steps.load(Ordering::Relaxed);
-    let touch = counters.touch_taps.load(Ordering::Relaxed);
-    let total = keys + clicks + scrolls + touch;
-
-    let secs = duration.as_secs();
-    let mins = secs / 60;
-    let secs_remainder = secs % 60;
-
-    let duration_str = if mins > 0 {
-        format!("{}m {}s", mins, secs_remainder)
+/// Ends the session early: prints the summary and exits.
+pub fn stop(controls: &Controls) -> ! {
+    controls.stop_display();
+    // Clear the live output line
+    eprintln!();
+    let within_budget = finish(controls);
+    exit(if within_budget {
+        0
     } else {
-        format!("{}s", secs)
-    };
+        budget::EXIT_BUDGET_EXCEEDED
+    });
+}
 
-    let apm = if duration.as_secs_f64() > 0.0 {
-        (total as f64 / duration.as_secs_f64()) * 60.0
-    } else {
-        0.0
-    };
+/// Prints the summary, saves it to the history and cleans up. Returns
+/// whether the session stayed within its budget.
+fn finish(controls: &Controls) -> bool {
+    let summary = controls.summary();
+    if let Some(path) = &controls.recording
+        && let Err(err) = controls
+            .session
+            .borrow_mut()
+            .finish_recording(Instant::now())
+    {
+        let err = ActionsError::WriteRecording(path.clone(), err);
+        eprintln!("Could not save the recording: {}", Report::new(err));
+    }
+    let _ = summary::write(&mut std::io::stderr(), &summary);
+    let checks = controls.budget.borrow().check(&summary);
+    if !checks.is_empty() {
+        let _ = budget::write(&mut std::io::stderr(), &checks);
+    }
+    if let Some(path) = &controls.summary_json {
+        let json = serde_json::to_string_pretty(&summary).unwrap();
+        if let Err(err) = std::fs::write(path, json + "\n") {
+            let err = ActionsError::WriteSummary(path.clone(), err);
+            eprintln!("Could not save the summary: {}", Report::new(err));
+        }
+    }
+    {
+        let clicks = controls.clicks.borrow();
+        if let Some(path) = &controls.click_heatmap {
+            save_export(path, |out| clicks::write_svg(out, &clicks));
+        }
+        if let Some(path) = &controls.click_csv {
+            save_export(path, |out| clicks::write_csv(out, &clicks));
+        }
+    }
+    if let Some(path) = &controls.keyboard_heatmap {
+        let session = controls.session.borrow();
+        save_export(path, |out| {
+            keyboard::write_svg(out, session.key_presses(), controls.keyboard_layout)
+        });
+    }
+    if let Some(path) = &controls.history_db {
+        match History::open(path).and_then(|mut history| history.insert(&summary)) {
+            Ok(id) => eprintln!("Saved as session {} in {}", id, path.display()),
+            Err(err) => eprintln!("Could not save the session: {}", Report::new(err)),
+        }
+    }
+    if let Some(path) = &controls.socket_path {
+        let _ = std::fs::remove_file(path);
+    }
+    checks.iter().all(Check::passed)
+}
 
-    eprintln!("\n=== Action Summary ===");
-    eprintln!("Duration: {}", duration_str);
-    eprintln!("Key presses: {}", keys);
-    eprintln!("Button clicks: {}", clicks);
-    eprintln!("Scroll steps: {} (tracked separately)", scrolls);
-    eprintln!("Touch taps: {}", touch);
-    eprintln!("Total actions: {} (keys + clicks)", total);
-    eprintln!("Actions per minute: {:.1}", apm);
+fn save_export(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) {
+    let result = File::create(path).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        write(&mut out)?;
+        out.flush()
+    });
+    if let Err(err) = result {
+        let err = ActionsError::WriteExport(path.to_owned(), err);
+        eprintln!("Could not save the export: {}", Report::new(err));
+    }
 }
 
 // Handler implementations
 
 struct WlDisplayHandlerImpl {
-    counters: Arc<ActionCounters>,
-    pressed_keys: Arc<Mutex<HashSet<u32>>>,
-    pressed_buttons: Arc<Mutex<HashSet<u32>>>,
-    last_scroll_time: Arc<Mutex<Instant>>,
+    controls: Controls,
 }
 
 impl WlDisplayHandler for WlDisplayHandlerImpl {
+    fn handle_sync(&mut self, slf: &Rc<WlDisplay>, callback: &Rc<WlCallback>) {
+        // The other threads sync to have their jobs run
+        self.controls.run_jobs();
+        slf.send_sync(callback);
+    }
+
     fn handle_get_registry(&mut self, slf: &Rc<WlDisplay>, registry: &Rc<WlRegistry>) {
         registry.set_handler(WlRegistryHandlerImpl {
-            counters: self.counters.clone(),
-            pressed_keys: self.pressed_keys.clone(),
-            pressed_buttons: self.pressed_buttons.clone(),
-            last_scroll_time: self.last_scroll_time.clone(),
+            controls: self.controls.clone(),
         });
         slf.send_get_registry(registry);
     }
 }
 
 struct WlRegistryHandlerImpl {
-    counters: Arc<ActionCounters>,
-    pressed_keys: Arc<Mutex<HashSet<u32>>>,
-    pressed_buttons: Arc<Mutex<HashSet<u32>>>,
-    last_scroll_time: Arc<Mutex<Instant>>,
+    controls: Controls,
 }
 
 impl WlRegistryHandler for WlRegistryHandlerImpl {
@@ -218,63 +433,295 @@ impl WlRegistryHandler for WlRegistryHandlerImpl {
     }
 
     fn handle_bind(&mut self, slf: &Rc<WlRegistry>, name: u32, object: Rc<dyn Object>) {
+        // Track window sizes and titles for the click heatmap
+        if object.core().interface() == ObjectInterface::XdgWmBase
+            && let Ok(wm_base) = (object.clone() as Rc<dyn Any>).downcast::<XdgWmBase>()
+        {
+            wm_base.set_handler(XdgWmBaseHandlerImpl {
+                controls: self.controls.clone(),
+            });
+        }
+        // Keep passwords out of the per-key detail
+        if object.core().interface() == ObjectInterface::ZwpTextInputManagerV3
+            && let Ok(manager) = (object.clone() as Rc<dyn Any>).downcast::<ZwpTextInputManagerV3>()
+        {
+            manager.set_handler(TextInputManagerHandlerImpl {
+                controls: self.controls.clone(),
+            });
+        }
         // Set counting handlers for wl_seat
         if object.core().interface() == ObjectInterface::WlSeat
             && let Ok(seat) = (object.clone() as Rc<dyn Any>).downcast::<WlSeat>()
         {
-            eprintln!("[DEBUG] Creating seat handler");
+            static SEAT_COUNTER: AtomicU32 = AtomicU32::new(0);
+            let seat_id = SEAT_COUNTER.fetch_add(1, Ordering::Relaxed);
+            if self.controls.verbose {
+                eprintln!("[DEBUG] Creating seat handler #{}", seat_id);
+            }
             seat.set_handler(CountingSeatHandler {
-                counters: self.counters.clone(),
-                pressed_keys: self.pressed_keys.clone(),
-                pressed_buttons: self.pressed_buttons.clone(),
-                last_scroll_time: self.last_scroll_time.clone(),
+                controls: self.controls.clone(),
+                seat: seat_id,
             });
         }
         slf.send_bind(name, object);
     }
 }
 
+struct XdgWmBaseHandlerImpl {
+    controls: Controls,
+}
+
+impl XdgWmBaseHandler for XdgWmBaseHandlerImpl {
+    fn handle_get_xdg_surface(
+        &mut self,
+        slf: &Rc<XdgWmBase>,
+        id: &Rc<XdgSurface>,
+        surface: &Rc<WlSurface>,
+    ) {
+        id.set_handler(XdgSurfaceHandlerImpl {
+            surface: surface_key(surface),
+            controls: self.controls.clone(),
+        });
+        slf.send_get_xdg_surface(id, surface);
+    }
+}
+
+struct XdgSurfaceHandlerImpl {
+    surface: SurfaceKey,
+    controls: Controls,
+}
+
+impl XdgSurfaceHandler for XdgSurfaceHandlerImpl {
+    fn handle_get_toplevel(&mut self, slf: &Rc<XdgSurface>, id: &Rc<XdgToplevel>) {
+        id.set_handler(XdgToplevelHandlerImpl {
+            surface: self.surface,
+            controls: self.controls.clone(),
+        });
+        slf.send_get_toplevel(id);
+    }
+
+    fn handle_set_window_geometry(
+        &mut self,
+        slf: &Rc<XdgSurface>,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    ) {
+        self.controls
+            .clicks
+            .borrow_mut()
+            .set_geometry(self.surface, x, y, width, height);
+        slf.send_set_window_geometry(x, y, width, height);
+    }
+
+    fn handle_destroy(&mut self, slf: &Rc<XdgSurface>) {
+        self.controls.clicks.borrow_mut().forget(self.surface);
+        slf.send_destroy();
+    }
+}
+
+struct XdgToplevelHandlerImpl {
+    surface: SurfaceKey,
+    controls: Controls,
+}
+
+impl XdgToplevelHandler for XdgToplevelHandlerImpl {
+    fn handle_set_title(&mut self, slf: &Rc<XdgToplevel>, title: &str) {
+        self.controls
+            .clicks
+            .borrow_mut()
+            .set_title(self.surface, title);
+        slf.send_set_title(title);
+    }
+
+    fn handle_set_app_id(&mut self, slf: &Rc<XdgToplevel>, app_id: &str) {
+        self.controls
+            .clicks
+            .borrow_mut()
+            .set_app_id(self.surface, app_id);
+        self.controls.set_app_id(app_id);
+        slf.send_set_app_id(app_id);
+    }
+
+    fn handle_configure(&mut self, slf: &Rc<XdgToplevel>, width: i32, height: i32, states: &[u8]) {
+        self.controls
+            .clicks
+            .borrow_mut()
+            .set_size(self.surface, width, height);
+        slf.send_configure(width, height, states);
+    }
+}
+
+struct TextInputManagerHandlerImpl {
+    controls: Controls,
+}
+
+impl ZwpTextInputManagerV3Handler for TextInputManagerHandlerImpl {
+    fn handle_get_text_input(
+        &mut self,
+        slf: &Rc<ZwpTextInputManagerV3>,
+        id: &Rc<ZwpTextInputV3>,
+        seat: &Rc<WlSeat>,
+    ) {
+        static TEXT_INPUT_COUNTER: AtomicU64 = AtomicU64::new(0);
+        id.set_handler(TextInputHandlerImpl {
+            controls: self.controls.clone(),
+            id: TEXT_INPUT_COUNTER.fetch_add(1, Ordering::Relaxed),
+            enabled: false,
+            purpose: ZwpTextInputV3ContentPurpose::NORMAL,
+        });
+        slf.send_get_text_input(id, seat);
+    }
+}
+
+/// Tracks the pending state of a text input; the session learns whether it
+/// takes a password when the state is committed.
+struct TextInputHandlerImpl {
+    controls: Controls,
+    id: u64,
+    enabled: bool,
+    purpose: ZwpTextInputV3ContentPurpose,
+}
+
+impl TextInputHandlerImpl {
+    fn set_sensitive(&self, sensitive: bool) {
+        self.controls
+            .session
+            .borrow_mut()
+            .set_sensitive(self.id, sensitive);
+    }
+}
+
+impl ZwpTextInputV3Handler for TextInputHandlerImpl {
+    fn handle_enable(&mut self, slf: &Rc<ZwpTextInputV3>) {
+        // Enabling resets the state, the content type included
+        self.enabled = true;
+        self.purpose = ZwpTextInputV3ContentPurpose::NORMAL;
+        slf.send_enable();
+    }
+
+    fn handle_disable(&mut self, slf: &Rc<ZwpTextInputV3>) {
+        self.enabled = false;
+        slf.send_disable();
+    }
+
+    fn handle_set_content_type(
+        &mut self,
+        slf: &Rc<ZwpTextInputV3>,
+        hint: ZwpTextInputV3ContentHint,
+        purpose: ZwpTextInputV3ContentPurpose,
+    ) {
+        self.purpose = purpose;
+        slf.send_set_content_type(hint, purpose);
+    }
+
+    fn handle_commit(&mut self, slf: &Rc<ZwpTextInputV3>) {
+        let password = matches!(
+            self.purpose,
+            ZwpTextInputV3ContentPurpose::PASSWORD | ZwpTextInputV3ContentPurpose::PIN
+        );
+        self.set_sensitive(self.enabled && password);
+        slf.send_commit();
+    }
+
+    fn handle_destroy(&mut self, slf: &Rc<ZwpTextInputV3>) {
+        self.set_sensitive(false);
+        slf.send_destroy();
+    }
+}
+
+impl Drop for TextInputHandlerImpl {
+    fn drop(&mut self) {
+        self.set_sensitive(false);
+    }
+}
+
+/// Surfaces are told apart by their proxy object.
+fn surface_key(surface: &Rc<WlSurface>) -> SurfaceKey {
+    Rc::as_ptr(surface) as SurfaceKey
+}
+
 struct CountingSeatHandler {
-    counters: Arc<ActionCounters>,
-    pressed_keys: Arc<Mutex<HashSet<u32>>>,
-    pressed_buttons: Arc<Mutex<HashSet<u32>>>,
-    last_scroll_time: Arc<Mutex<Instant>>,
+    controls: Controls,
+    /// Numbers the seats in the order they were bound.
+    seat: u32,
+}
+
+impl CountingSeatHandler {
+    /// A source for a new device object on this seat.
+    fn new_source(&self) -> Source {
+        static DEVICE_COUNTER: AtomicU64 = AtomicU64::new(0);
+        Source {
+            seat: self.seat,
+            device: DEVICE_COUNTER.fetch_add(1, Ordering::Relaxed),
+        }
+    }
 }
 
 impl WlSeatHandler for CountingSeatHandler {
+    fn handle_name(&mut self, slf: &Rc<WlSeat>, name: &str) {
+        self.controls
+            .session
+            .borrow_mut()
+            .set_seat_name(self.seat, name);
+        slf.send_name(name);
+    }
+
     fn handle_get_pointer(&mut self, slf: &Rc<WlSeat>, id: &Rc<WlPointer>) {
-        use std::sync::atomic::AtomicU64;
-        static POINTER_COUNTER: AtomicU64 = AtomicU64::new(0);
-        let ptr_id = POINTER_COUNTER.fetch_add(1, Ordering::Relaxed);
-        eprintln!("[DEBUG] Creating pointer handler #{}", ptr_id);
+        let source = self.new_source();
+        if self.controls.verbose {
+            eprintln!(
+                "[DEBUG] Creating pointer handler #{} on seat #{}",
+                source.device, source.seat
+            );
+        }
         id.set_handler(CountingPointerHandler {
-            counters: self.counters.clone(),
-            pressed_buttons: self.pressed_buttons.clone(),
-            last_scroll_time: self.last_scroll_time.clone(),
-            handler_id: ptr_id,
+            controls: self.controls.clone(),
+            source,
+            focus: None,
         });
         slf.send_get_pointer(id);
     }
 
     fn handle_get_keyboard(&mut self, slf: &Rc<WlSeat>, id: &Rc<WlKeyboard>) {
         id.set_handler(CountingKeyboardHandler {
-            counters: self.counters.clone(),
-            pressed_keys: self.pressed_keys.clone(),
+            controls: self.controls.clone(),
+            source: self.new_source(),
+            focus: None,
+            swallowed_keys: HashSet::new(),
         });
         slf.send_get_keyboard(id);
     }
 
     fn handle_get_touch(&mut self, slf: &Rc<WlSeat>, id: &Rc<WlTouch>) {
         id.set_handler(CountingTouchHandler {
-            counters: self.counters.clone(),
+            controls: self.controls.clone(),
+            source: self.new_source(),
         });
         slf.send_get_touch(id);
     }
 }
 
+/// Feeds an event to the session, counting it for `surface` as well. The
+/// session tracks press state per seat across all of its keyboards and
+/// pointers, so duplicate events from several handlers are only counted once.
+fn handle_event(
+    controls: &Controls,
+    source: Source,
+    surface: Option<SurfaceKey>,
+    event: Event,
+) -> Option<Action> {
+    controls.handle_event(source, surface, event, Instant::now())
+}
+
 struct CountingKeyboardHandler {
-    counters: Arc<ActionCounters>,
-    pressed_keys: Arc<Mutex<HashSet<u32>>>,
+    controls: Controls,
+    source: Source,
+    /// The surface with keyboard focus.
+    focus: Option<SurfaceKey>,
+    /// Hotkey presses withheld from this keyboard's client.
+    swallowed_keys: HashSet<u32>,
 }
 
 impl WlKeyboardHandler for CountingKeyboardHandler {
@@ -286,32 +733,173 @@ impl WlKeyboardHandler for CountingKeyboardHandler {
         key: u32,
         state: WlKeyboardKeyState,
     ) {
-        // Track key state to distinguish initial press from repeats
-        // Use shared pressed_keys so multiple handlers don't double-count
-        match state {
+        let input = Input::Key(key);
+        let seen = match state {
+            WlKeyboardKeyState::PRESSED => self.controls.filter_press(self.source, input),
+            WlKeyboardKeyState::RELEASED => self
+                .controls
+                .filter
+                .borrow_mut()
+                .release(self.source, input),
+            _ => self.controls.filter.borrow().repeat(self.source, input),
+        };
+        // Filtered keys are neither counted nor sent to the client
+        let Some(Input::Key(key)) = seen else {
+            return;
+        };
+        let forward = match state {
             WlKeyboardKeyState::PRESSED => {
-                let mut pressed = self.pressed_keys.lock().unwrap();
-                // Only count if this key wasn't already pressed (ignore repeats and duplicates)
-                if pressed.insert(key) {
-                    self.counters.key_presses.fetch_add(1, Ordering::Relaxed);
+                // Hotkeys control the session instead of counting as an
+                // action. Repeats and duplicates of a held key are not counted
+                let hotkey =
+                    self.controls
+                        .key_pressed(self.source, self.focus, key, Instant::now());
+                if hotkey && self.controls.hotkeys.get().swallow {
+                    self.swallowed_keys.insert(key);
                 }
+                !self.swallowed_keys.contains(&key)
             }
             WlKeyboardKeyState::RELEASED => {
-                let mut pressed = self.pressed_keys.lock().unwrap();
-                // Remove from pressed set when released
-                pressed.remove(&key);
+                handle_event(
+                    &self.controls,
+                    self.source,
+                    self.focus,
+                    Event::KeyReleased { key },
+                );
+                !self.swallowed_keys.remove(&key)
             }
-            _ => {}
+            WlKeyboardKeyState::REPEATED => {
+                if !self.swallowed_keys.contains(&key) {
+                    handle_event(
+                        &self.controls,
+                        self.source,
+                        self.focus,
+                        Event::KeyRepeated { key },
+                    );
+                }
+                !self.swallowed_keys.contains(&key)
+            }
+            _ => !self.swallowed_keys.contains(&key),
+        };
+        if forward {
+            slf.send_key(serial, time, key, state);
         }
-        slf.send_key(serial, time, key, state);
+    }
+
+    fn handle_enter(
+        &mut self,
+        slf: &Rc<WlKeyboard>,
+        serial: u32,
+        surface: &Rc<WlSurface>,
+        keys: &[u8],
+    ) {
+        self.focus = Some(surface_key(surface));
+        // Keys may have gone up or down while another client had focus
+        let held: Vec<u32> = keys
+            .chunks_exact(4)
+            .map(|key| u32::from_ne_bytes(key.try_into().unwrap()))
+            .collect();
+        self.controls.count_deferred();
+        self.controls
+            .session
+            .borrow_mut()
+            .sync_keys(self.source, &held, Instant::now());
+        slf.send_enter(serial, surface, keys);
+    }
+
+    fn handle_repeat_info(&mut self, slf: &Rc<WlKeyboard>, rate: i32, delay: i32) {
+        let info = RepeatInfo {
+            rate: rate.max(0) as u32,
+            delay: Duration::from_millis(delay.max(0) as u64),
+        };
+        self.controls
+            .session
+            .borrow_mut()
+            .set_repeat_info(self.source, info);
+        slf.send_repeat_info(rate, delay);
+    }
+
+    fn handle_leave(&mut self, slf: &Rc<WlKeyboard>, serial: u32, surface: &Rc<WlSurface>) {
+        // The releases of held keys go to the next client with focus
+        self.focus = None;
+        self.release_keys();
+        slf.send_leave(serial, surface);
+    }
+
+    fn handle_release(&mut self, slf: &Rc<WlKeyboard>) {
+        self.release_keys();
+        slf.send_release();
+    }
+}
+
+impl CountingKeyboardHandler {
+    fn release_keys(&mut self) {
+        self.controls.count_deferred();
+        self.controls
+            .session
+            .borrow_mut()
+            .release_device(self.source, Instant::now());
+        self.controls
+            .filter
+            .borrow_mut()
+            .release_device(self.source);
+        self.swallowed_keys.clear();
+    }
+}
+
+impl Drop for CountingKeyboardHandler {
+    fn drop(&mut self) {
+        self.release_keys();
     }
 }
 
 struct CountingPointerHandler {
-    counters: Arc<ActionCounters>,
-    pressed_buttons: Arc<Mutex<HashSet<u32>>>,
-    last_scroll_time: Arc<Mutex<Instant>>,
-    handler_id: u64,
+    controls: Controls,
+    source: Source,
+    /// The surface under the pointer and the position on it.
+    focus: Option<(SurfaceKey, f64, f64)>,
+}
+
+impl CountingPointerHandler {
+    fn surface(&self) -> Option<SurfaceKey> {
+        self.focus.map(|(surface, _, _)| surface)
+    }
+
+    /// Releases the buttons held on this pointer, which will not see their
+    /// releases after leaving or being destroyed.
+    fn release_buttons(&self) {
+        self.controls
+            .session
+            .borrow_mut()
+            .release_device(self.source, Instant::now());
+        self.controls
+            .filter
+            .borrow_mut()
+            .release_device(self.source);
+    }
+
+    fn scroll(&self, what: std::fmt::Arguments<'_>) {
+        let counted =
+            handle_event(&self.controls, self.source, self.surface(), Event::Scroll).is_some();
+        if self.controls.verbose {
+            eprintln!(
+                "[DEBUG] Handler #{}: {} ({})",
+                self.source.device,
+                what,
+                if counted {
+                    "counted"
+                } else {
+                    "ignored - too soon"
+                }
+            );
+        }
+    }
+}
+
+impl Drop for CountingPointerHandler {
+    fn drop(&mut self) {
+        self.release_buttons();
+    }
 }
 
 impl WlPointerHandler for CountingPointerHandler {
@@ -323,114 +911,136 @@ impl WlPointerHandler for CountingPointerHandler {
         button: u32,
         state: WlPointerButtonState,
     ) {
-        // Track button state to avoid double-counting with multiple handlers
+        let input = Input::Button(button);
+        let seen = match state {
+            WlPointerButtonState::PRESSED => self.controls.filter_press(self.source, input),
+            WlPointerButtonState::RELEASED => self
+                .controls
+                .filter
+                .borrow_mut()
+                .release(self.source, input),
+            _ => Some(input),
+        };
+        let Some(Input::Button(button)) = seen else {
+            return;
+        };
         match state {
             WlPointerButtonState::PRESSED => {
-                let mut pressed = self.pressed_buttons.lock().unwrap();
-                // Only count if this button wasn't already pressed
-                let was_new = pressed.insert(button);
-                eprintln!(
-                    "[DEBUG] Handler #{}: Button {} pressed, was_new={}, count now={}",
-                    self.handler_id,
-                    button,
-                    was_new,
-                    self.counters.button_clicks.load(Ordering::Relaxed)
-                        + if was_new { 1 } else { 0 }
+                let counted = handle_event(
+                    &self.controls,
+                    self.source,
+                    self.surface(),
+                    Event::ButtonPressed { button },
                 );
-                if was_new {
-                    self.counters.button_clicks.fetch_add(1, Ordering::Relaxed);
+                if self.controls.verbose {
+                    eprintln!(
+                        "[DEBUG] Handler #{}: Button {} pressed, counted={}",
+                        self.source.device,
+                        button,
+                        counted.is_some()
+                    );
+                }
+                if counted.is_some()
+                    && let Some((surface, x, y)) = self.focus
+                {
+                    self.controls
+                        .record_click(surface, x, y, ClickKind::Click(button));
                 }
             }
             WlPointerButtonState::RELEASED => {
-                let mut pressed = self.pressed_buttons.lock().unwrap();
-                eprintln!(
-                    "[DEBUG] Handler #{}: Button {} released",
-                    self.handler_id, button
+                handle_event(
+                    &self.controls,
+                    self.source,
+                    None,
+                    Event::ButtonReleased { button },
                 );
-                pressed.remove(&button);
+                if self.controls.verbose {
+                    eprintln!(
+                        "[DEBUG] Handler #{}: Button {} released",
+                        self.source.device, button
+                    );
+                }
             }
             _ => {}
         }
         slf.send_button(serial, time, button, state);
     }
 
+    fn handle_enter(
+        &mut self,
+        slf: &Rc<WlPointer>,
+        serial: u32,
+        surface: &Rc<WlSurface>,
+        surface_x: Fixed,
+        surface_y: Fixed,
+    ) {
+        let (x, y) = (surface_x.to_f64(), surface_y.to_f64());
+        self.focus = Some((surface_key(surface), x, y));
+        handle_event(
+            &self.controls,
+            self.source,
+            None,
+            Event::PointerEnter { x, y },
+        );
+        slf.send_enter(serial, surface, surface_x, surface_y);
+    }
+
+    fn handle_leave(&mut self, slf: &Rc<WlPointer>, serial: u32, surface: &Rc<WlSurface>) {
+        self.focus = None;
+        self.release_buttons();
+        slf.send_leave(serial, surface);
+    }
+
+    fn handle_release(&mut self, slf: &Rc<WlPointer>) {
+        self.release_buttons();
+        slf.send_release();
+    }
+
+    fn handle_motion(
+        &mut self,
+        slf: &Rc<WlPointer>,
+        time: u32,
+        surface_x: Fixed,
+        surface_y: Fixed,
+    ) {
+        let (x, y) = (surface_x.to_f64(), surface_y.to_f64());
+        if let Some((_, focus_x, focus_y)) = &mut self.focus {
+            (*focus_x, *focus_y) = (x, y);
+        }
+        handle_event(
+            &self.controls,
+            self.source,
+            None,
+            Event::PointerMotion { x, y },
+        );
+        slf.send_motion(time, surface_x, surface_y);
+    }
+
     fn handle_axis(&mut self, slf: &Rc<WlPointer>, time: u32, axis: WlPointerAxis, value: Fixed) {
-        // Basic axis events for scrolling - throttle to avoid counting every micro-event
+        // Smooth scrolling sends bursts of axis events; the session debounces them
         if matches!(
             axis,
             WlPointerAxis::VERTICAL_SCROLL | WlPointerAxis::HORIZONTAL_SCROLL
         ) {
-            let mut last_time = self.last_scroll_time.lock().unwrap();
-            let now = Instant::now();
-            let elapsed = now.duration_since(*last_time);
-
-            // Only count if at least 100ms has passed since last scroll (debounce)
-            if elapsed >= Duration::from_millis(100) {
-                eprintln!(
-                    "[DEBUG] Handler #{}: Axis scroll event (value={}, counted)",
-                    self.handler_id,
-                    value.to_f64()
-                );
-                self.counters.scroll_steps.fetch_add(1, Ordering::Relaxed);
-                *last_time = now;
-            } else {
-                eprintln!(
-                    "[DEBUG] Handler #{}: Axis scroll event (value={}, ignored - too soon)",
-                    self.handler_id,
-                    value.to_f64()
-                );
-            }
+            self.scroll(format_args!("Axis scroll event (value={})", value.to_f64()));
         }
         slf.send_axis(time, axis, value);
     }
 
     fn handle_axis_discrete(&mut self, slf: &Rc<WlPointer>, axis: WlPointerAxis, discrete: i32) {
-        // Discrete scroll - throttle to avoid double counting
-        let mut last_time = self.last_scroll_time.lock().unwrap();
-        let now = Instant::now();
-        let elapsed = now.duration_since(*last_time);
-
-        if elapsed >= Duration::from_millis(100) {
-            eprintln!(
-                "[DEBUG] Handler #{}: Discrete scroll (discrete={}, counted)",
-                self.handler_id, discrete
-            );
-            self.counters.scroll_steps.fetch_add(1, Ordering::Relaxed);
-            *last_time = now;
-        } else {
-            eprintln!(
-                "[DEBUG] Handler #{}: Discrete scroll (discrete={}, ignored - too soon)",
-                self.handler_id, discrete
-            );
-        }
+        self.scroll(format_args!("Discrete scroll (discrete={})", discrete));
         slf.send_axis_discrete(axis, discrete);
     }
 
     fn handle_axis_value120(&mut self, slf: &Rc<WlPointer>, axis: WlPointerAxis, value120: i32) {
-        // High-resolution scroll - throttle to avoid double counting
-        let mut last_time = self.last_scroll_time.lock().unwrap();
-        let now = Instant::now();
-        let elapsed = now.duration_since(*last_time);
-
-        if elapsed >= Duration::from_millis(100) {
-            eprintln!(
-                "[DEBUG] Handler #{}: Value120 scroll (value120={}, counted)",
-                self.handler_id, value120
-            );
-            self.counters.scroll_steps.fetch_add(1, Ordering::Relaxed);
-            *last_time = now;
-        } else {
-            eprintln!(
-                "[DEBUG] Handler #{}: Value120 scroll (value120={}, ignored - too soon)",
-                self.handler_id, value120
-            );
-        }
+        self.scroll(format_args!("Value120 scroll (value120={})", value120));
         slf.send_axis_value120(axis, value120);
     }
 }
 
 struct CountingTouchHandler {
-    counters: Arc<ActionCounters>,
+    controls: Controls,
+    source: Source,
 }
 
 impl WlTouchHandler for CountingTouchHandler {
@@ -445,114 +1055,11 @@ impl WlTouchHandler for CountingTouchHandler {
         y: Fixed,
     ) {
         // Count each touch down as an action
-        self.counters.touch_taps.fetch_add(1, Ordering::Relaxed);
-        slf.send_down(serial, time, surface, id, x, y);
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_action_counters_basic() {
-        let counters = ActionCounters::new();
-
-        // Test initial state
-        assert_eq!(counters.key_presses.load(Ordering::Relaxed), 0);
-        assert_eq!(counters.button_clicks.load(Ordering::Relaxed), 0);
-        assert_eq!(counters.scroll_steps.load(Ordering::Relaxed), 0);
-        assert_eq!(counters.touch_taps.load(Ordering::Relaxed), 0);
-        assert_eq!(counters.total(), 0);
-
-        // Test incrementing
-        counters.key_presses.fetch_add(5, Ordering::Relaxed);
-        counters.button_clicks.fetch_add(3, Ordering::Relaxed);
-        assert_eq!(counters.key_presses.load(Ordering::Relaxed), 5);
-        assert_eq!(counters.button_clicks.load(Ordering::Relaxed), 3);
-        assert_eq!(counters.total(), 8);
-    }
-
-    #[test]
-    fn test_shared_pressed_keys() {
-        // Test that shared pressed_keys prevents double-counting
-        let pressed_keys = Arc::new(Mutex::new(HashSet::new()));
-        let counters = Arc::new(ActionCounters::new());
-
-        // Simulate two handlers processing the same key press
-        {
-            let mut pressed = pressed_keys.lock().unwrap();
-            if pressed.insert(42) {
-                counters.key_presses.fetch_add(1, Ordering::Relaxed);
-            }
+        let key = surface_key(surface);
+        if handle_event(&self.controls, self.source, Some(key), Event::TouchDown).is_some() {
+            self.controls
+                .record_click(key, x.to_f64(), y.to_f64(), ClickKind::Tap);
         }
-
-        {
-            let mut pressed = pressed_keys.lock().unwrap();
-            if pressed.insert(42) {
-                counters.key_presses.fetch_add(1, Ordering::Relaxed);
-            }
-        }
-
-        // Should only count once
-        assert_eq!(counters.key_presses.load(Ordering::Relaxed), 1);
-
-        // Release the key
-        {
-            let mut pressed = pressed_keys.lock().unwrap();
-            pressed.remove(&42);
-        }
-
-        // Press again should count
-        {
-            let mut pressed = pressed_keys.lock().unwrap();
-            if pressed.insert(42) {
-                counters.key_presses.fetch_add(1, Ordering::Relaxed);
-            }
-        }
-
-        assert_eq!(counters.key_presses.load(Ordering::Relaxed), 2);
-    }
-
-    #[test]
-    fn test_shared_pressed_buttons() {
-        // Test that shared pressed_buttons prevents double-counting clicks
-        let pressed_buttons = Arc::new(Mutex::new(HashSet::new()));
-        let counters = Arc::new(ActionCounters::new());
-
-        // Simulate two handlers processing the same button press
-        {
-            let mut pressed = pressed_buttons.lock().unwrap();
-            if pressed.insert(272) {
-                // BTN_LEFT
-                counters.button_clicks.fetch_add(1, Ordering::Relaxed);
-            }
-        }
-
-        {
-            let mut pressed = pressed_buttons.lock().unwrap();
-            if pressed.insert(272) {
-                counters.button_clicks.fetch_add(1, Ordering::Relaxed);
-            }
-        }
-
-        // Should only count once
-        assert_eq!(counters.button_clicks.load(Ordering::Relaxed), 1);
-
-        // Release the button
-        {
-            let mut pressed = pressed_buttons.lock().unwrap();
-            pressed.remove(&272);
-        }
-
-        // Press again should count
-        {
-            let mut pressed = pressed_buttons.lock().unwrap();
-            if pressed.insert(272) {
-                counters.button_clicks.fetch_add(1, Ordering::Relaxed);
-            }
-        }
-
-        assert_eq!(counters.button_clicks.load(Ordering::Relaxed), 2);
+        slf.send_down(serial, time, surface, id, x, y);
     }
 }
@@ -0,0 +1,313 @@
+//! Limits on the final counters, for failing UI tests that got more tedious.
+
+use {
+    serde::Deserialize,
+    std::{
+        fmt,
+        io::{self, Write},
+        str::FromStr,
+    },
+    thiserror::Error,
+    wl_actions::summary::Summary,
+};
+
+/// The exit status when a budget is exceeded, distinct from the status 1 of
+/// other errors.
+pub const EXIT_BUDGET_EXCEEDED: i32 = 3;
+
+#[derive(Debug, Error)]
+pub enum BudgetError {
+    #[error("`{0}` is not of the form `<metric><op><value>`, e.g. `keys<=40`")]
+    Syntax(String),
+    #[error("unknown metric `{0}`, expected keys, clicks, scrolls, touch, total or duration")]
+    UnknownMetric(String),
+    #[error("`{0}` is not a valid limit")]
+    InvalidValue(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    Keys,
+    Clicks,
+    Scrolls,
+    Touch,
+    Total,
+    /// The active duration in seconds.
+    Duration,
+}
+
+const METRIC_NAMES: &[(&str, Metric)] = &[
+    ("keys", Metric::Keys),
+    ("clicks", Metric::Clicks),
+    ("scrolls", Metric::Scrolls),
+    ("touch", Metric::Touch),
+    ("total", Metric::Total),
+    ("duration", Metric::Duration),
+];
+
+impl Metric {
+    fn name(self) -> &'static str {
+        METRIC_NAMES
+            .iter()
+            .find(|&&(_, metric)| metric == self)
+            .map(|&(name, _)| name)
+            .unwrap()
+    }
+
+    fn value(self, summary: &Summary) -> f64 {
+        let counters = &summary.counters;
+        match self {
+            Metric::Keys => counters.key_presses as f64,
+            Metric::Clicks => counters.button_clicks as f64,
+            Metric::Scrolls => counters.scroll_steps as f64,
+            Metric::Touch => counters.touch_taps as f64,
+            Metric::Total => summary.total_actions(),
+            Metric::Duration => summary.duration_secs,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+// Two-character operators first so that `<=` is not read as `<`.
+const OPS: &[(&str, Op)] = &[
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("==", Op::Eq),
+    ("<", Op::Lt),
+    (">", Op::Gt),
+    ("=", Op::Eq),
+];
+
+impl Op {
+    fn holds(self, actual: f64, limit: f64) -> bool {
+        match self {
+            Op::Lt => actual < limit,
+            Op::Le => actual <= limit,
+            Op::Eq => actual == limit,
+            Op::Ge => actual >= limit,
+            Op::Gt => actual > limit,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Eq => "==",
+            Op::Ge => ">=",
+            Op::Gt => ">",
+        }
+    }
+}
+
+/// A single assertion such as `clicks<=10`.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Limit {
+    pub metric: Metric,
+    pub op: Op,
+    pub value: f64,
+}
+
+impl FromStr for Limit {
+    type Err = BudgetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (pos, symbol, op) = OPS
+            .iter()
+            .filter_map(|&(symbol, op)| s.find(symbol).map(|pos| (pos, symbol, op)))
+            .min_by_key(|&(pos, _, _)| pos)
+            .ok_or_else(|| BudgetError::Syntax(s.to_string()))?;
+        let name = s[..pos].trim();
+        let value = s[pos + symbol.len()..].trim();
+        if name.is_empty() || value.is_empty() {
+            return Err(BudgetError::Syntax(s.to_string()));
+        }
+        let metric = METRIC_NAMES
+            .iter()
+            .find(|&&(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, metric)| metric)
+            .ok_or_else(|| BudgetError::UnknownMetric(name.to_string()))?;
+        let value = value
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite() && *value >= 0.0)
+            .ok_or_else(|| BudgetError::InvalidValue(value.to_string()))?;
+        Ok(Self { metric, op, value })
+    }
+}
+
+impl TryFrom<String> for Limit {
+    type Error = BudgetError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.metric.name(),
+            self.op.symbol(),
+            self.value
+        )
+    }
+}
+
+/// A set of limits from `--assert` and `--budget` files.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Budget {
+    pub limits: Vec<Limit>,
+}
+
+/// The outcome of one limit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Check {
+    pub limit: Limit,
+    pub actual: f64,
+}
+
+impl Check {
+    pub fn passed(&self) -> bool {
+        self.limit.op.holds(self.actual, self.limit.value)
+    }
+}
+
+impl Budget {
+    /// Parses a budget file: limits separated by commas or newlines, with `#`
+    /// starting a comment.
+    pub fn parse(text: &str) -> Result<Self, BudgetError> {
+        let limits = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap())
+            .flat_map(|line| line.split(','))
+            .filter(|limit| !limit.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { limits })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.limits.is_empty()
+    }
+
+    pub fn check(&self, summary: &Summary) -> Vec<Check> {
+        self.limits
+            .iter()
+            .map(|&limit| Check {
+                limit,
+                actual: limit.metric.value(summary),
+            })
+            .collect()
+    }
+}
+
+/// Writes every limit with its actual value and, for failed ones, by how much
+/// the limit was missed.
+pub fn write(out: &mut impl Write, checks: &[Check]) -> io::Result<()> {
+    let passed = checks.iter().all(Check::passed);
+    writeln!(
+        out,
+        "\n=== Budget {} ===",
+        if passed { "met" } else { "exceeded" }
+    )?;
+    let width = checks
+        .iter()
+        .map(|check| check.limit.to_string().len())
+        .max()
+        .unwrap_or_default();
+    for check in checks {
+        let limit = check.limit.to_string();
+        if check.passed() {
+            writeln!(
+                out,
+                "  ok    {:<width$}  actual {}",
+                limit,
+                format_value(check.actual)
+            )?;
+        } else {
+            writeln!(
+                out,
+                "  FAIL  {:<width$}  actual {} ({}{})",
+                limit,
+                format_value(check.actual),
+                if check.actual > check.limit.value {
+                    "+"
+                } else {
+                    "-"
+                },
+                format_value((check.actual - check.limit.value).abs())
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value)
+    } else {
+        format!("{:.1}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::fixtures::summary};
+
+    #[test]
+    fn test_parse_limits() {
+        let limit: Limit = " clicks <= 10 ".parse().unwrap();
+        assert_eq!(limit.metric, Metric::Clicks);
+        assert_eq!(limit.op, Op::Le);
+        assert_eq!(limit.value, 10.0);
+        assert_eq!(limit.to_string(), "clicks <= 10");
+        assert_eq!("total>5".parse::<Limit>().unwrap().op, Op::Gt);
+        assert_eq!("keys=5".parse::<Limit>().unwrap().op, Op::Eq);
+
+        assert!(matches!(
+            "clicks".parse::<Limit>(),
+            Err(BudgetError::Syntax(_))
+        ));
+        assert!(matches!(
+            "mouse<=3".parse::<Limit>(),
+            Err(BudgetError::UnknownMetric(_))
+        ));
+        assert!(matches!(
+            "keys<=lots".parse::<Limit>(),
+            Err(BudgetError::InvalidValue(_))
+        ));
+
+        let budget =
+            Budget::parse("# login flow\nkeys<=40, clicks<=10\n\ntotal<=45 # ci\n").unwrap();
+        assert_eq!(budget.limits.len(), 3);
+    }
+
+    #[test]
+    fn test_check_budget() {
+        let budget = Budget::parse("keys<=40,clicks<=10,total<=45").unwrap();
+        let checks = budget.check(&summary("firefox", 0, 38, 13));
+        let passed: Vec<_> = checks.iter().map(Check::passed).collect();
+        assert_eq!(passed, [true, false, false]);
+
+        let mut out = Vec::new();
+        write(&mut out, &checks).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("=== Budget exceeded ==="));
+        assert!(out.contains("  ok    keys <= 40    actual 38\n"));
+        assert!(out.contains("  FAIL  clicks <= 10  actual 13 (+3)\n"));
+        assert!(out.contains("  FAIL  total <= 45   actual 51 (+6)\n"));
+    }
+}
@@ -1,15 +1,46 @@
 use {
-    crate::{ActionsError, actions},
-    clap::{CommandFactory, Parser, ValueHint},
+    crate::{
+        ActionsError,
+        actions::{self, Options},
+        budget::{self, Budget, Check, Limit},
+        compare,
+        config::{self, Config, Settings},
+        controls::{AppProfile, Hotkeys},
+        filter::Rule,
+        history::{self, History},
+        hotkey::Hotkey,
+        keyboard::KeyboardLayout,
+        socket,
+        status_bar::StatusBarFormat,
+    },
+    clap::{Args, CommandFactory, Parser, Subcommand, ValueHint},
     clap_complete::Shell,
-    std::io::stdout,
+    std::{
+        fs::{self, File},
+        io::{BufReader, IsTerminal, Write, stderr, stdout},
+        net::SocketAddr,
+        path::{Path, PathBuf},
+        process::exit,
+        time::Duration,
+    },
+    wl_actions::{
+        detector,
+        privacy::Privacy,
+        recording,
+        session::DEFAULT_SCROLL_DEBOUNCE,
+        summary::{self, Summary},
+        weights::Weights,
+    },
 };
 
 /// Count input actions (key presses, mouse clicks, scroll events, touch taps)
 /// for a wrapped Wayland application.
 #[derive(Parser, Debug)]
-#[command(name = "wl-actions")]
+#[command(name = "wl-actions", subcommand_negates_reqs = true)]
 pub struct WlActions {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Generate shell completions instead of running the program.
     #[clap(long, value_enum, value_name = "SHELL")]
     generate_completion: Option<Shell>,
@@ -18,6 +49,118 @@ pub struct WlActions {
     #[clap(short, long)]
     quiet: bool,
 
+    /// Show a full-screen dashboard on the terminal instead of the live line.
+    #[clap(long, conflicts_with = "quiet")]
+    dashboard: bool,
+
+    /// Log the seats, pointers, buttons and scrolls seen, for debugging.
+    #[clap(short, long, conflicts_with_all = ["quiet", "dashboard"])]
+    verbose: bool,
+
+    /// Names for the laps recorded with SIGUSR1, assigned in order.
+    #[clap(long, value_name = "NAME", value_delimiter = ',')]
+    lap_names: Vec<String>,
+
+    /// Key chord that records a lap, e.g. `Super+F12`. It is not counted.
+    #[clap(long, value_name = "CHORD")]
+    lap_hotkey: Option<Hotkey>,
+
+    /// Key chord that pauses and resumes counting, e.g. `Super+F11`.
+    #[clap(long, value_name = "CHORD")]
+    pause_hotkey: Option<Hotkey>,
+
+    /// Don't forward the hotkeys to the application.
+    #[clap(long)]
+    swallow_hotkeys: bool,
+
+    /// Drop, remap or rate-limit keys and buttons, e.g. `Caps_Lock=drop,ButtonMiddle=drop,BackSpace=5/s`.
+    #[clap(long, value_name = "RULES", value_delimiter = ',')]
+    filter: Vec<Rule>,
+
+    /// Listen for commands on a control socket at $XDG_RUNTIME_DIR/wl-actions-<PID>.sock.
+    #[clap(long)]
+    control: bool,
+
+    /// Listen for commands on a control socket at this path.
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    control_socket: Option<PathBuf>,
+
+    /// Continuously write the counters in a status bar format.
+    #[clap(long, value_enum, value_name = "FORMAT")]
+    status_bar: Option<StatusBarFormat>,
+
+    /// Write the status bar output to a file or FIFO instead of stdout.
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    status_bar_output: Option<PathBuf>,
+
+    /// Serve Prometheus metrics on this address, e.g. `127.0.0.1:9187`.
+    #[clap(long, value_name = "ADDR")]
+    metrics_listen: Option<SocketAddr>,
+
+    /// Save the session to the history database when it ends.
+    #[clap(long)]
+    history: bool,
+
+    /// Path of the history database [default: $XDG_DATA_HOME/wl-actions/history.sqlite]
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    history_db: Option<PathBuf>,
+
+    /// Write the summary as JSON to this file when the session ends.
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    summary_json: Option<PathBuf>,
+
+    /// Record the events to this file, to replay them with `wl-actions replay`.
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    record: Option<PathBuf>,
+
+    #[command(flatten)]
+    budget: BudgetArgs,
+
+    /// Write a heatmap of the clicks and taps on each window to this SVG file.
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    click_heatmap: Option<PathBuf>,
+
+    /// Write every click and tap with its position to this CSV file.
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    click_csv: Option<PathBuf>,
+
+    /// Write a heatmap of the presses of each key to this SVG file.
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    keyboard_heatmap: Option<PathBuf>,
+
+    /// The keyboard drawn by `--keyboard-heatmap` [default: ansi]
+    #[clap(long, value_name = "LAYOUT")]
+    keyboard_layout: Option<KeyboardLayout>,
+
+    /// How much key identity detailed output keeps: off, hash, classes or redact [default: off]
+    #[clap(long, value_name = "LEVEL")]
+    privacy: Option<Privacy>,
+
+    /// How much each kind of action counts towards the total, e.g. `scrolls=0.5`.
+    #[clap(long, value_name = "WEIGHTS")]
+    weights: Option<Weights>,
+
+    /// Count scroll events closer together than this as one step [default: 100]
+    #[clap(long, value_name = "MS")]
+    scroll_debounce_ms: Option<u64>,
+
+    /// Count the repeats of held keys as key presses, e.g. a held Backspace
+    /// as one press per deleted character.
+    #[clap(long)]
+    count_repeats: bool,
+
+    /// Read the configuration from this file [default: $XDG_CONFIG_HOME/wl-actions/config.toml]
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    config: Option<PathBuf>,
+
+    /// Don't read the configuration file.
+    #[clap(long, conflicts_with = "config")]
+    no_config: bool,
+
+    /// Use this profile of the configuration instead of the one matching the program.
+    #[clap(long, value_name = "NAME", conflicts_with = "no_config")]
+    profile: Option<String>,
+
     /// The program to run (and its arguments).
     #[clap(
         trailing_var_arg = true,
@@ -27,6 +170,141 @@ pub struct WlActions {
     program: Option<Vec<String>>,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show past sessions saved with `--history`.
+    History(HistoryArgs),
+    /// Compare two sessions, given as `--summary-json` files or history IDs.
+    Compare {
+        /// The baseline session.
+        a: String,
+
+        /// The session compared against the baseline.
+        b: String,
+
+        #[command(flatten)]
+        reweight: ReweightArgs,
+
+        /// Path of the history database [default: $XDG_DATA_HOME/wl-actions/history.sqlite]
+        #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+        history_db: Option<PathBuf>,
+    },
+    /// Check a saved session against a budget, without running anything.
+    Check {
+        /// The session, as a `--summary-json` file or a history ID.
+        session: String,
+
+        #[command(flatten)]
+        budget: BudgetArgs,
+
+        #[command(flatten)]
+        reweight: ReweightArgs,
+
+        /// Path of the history database [default: $XDG_DATA_HOME/wl-actions/history.sqlite]
+        #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+        history_db: Option<PathBuf>,
+    },
+    /// Replay a session recorded with `--record`, and check it against a budget if given one.
+    Replay {
+        /// The recording.
+        #[clap(value_hint = ValueHint::FilePath)]
+        recording: PathBuf,
+
+        #[command(flatten)]
+        budget: BudgetArgs,
+
+        #[command(flatten)]
+        reweight: ReweightArgs,
+
+        /// Write the summary as JSON to this file.
+        #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+        summary_json: Option<PathBuf>,
+    },
+}
+
+/// Limits on the final counters. When one is exceeded, wl-actions exits with
+/// status 3.
+#[derive(Args, Debug)]
+struct BudgetArgs {
+    /// Fail if the counters exceed these limits, e.g. `keys<=40,clicks<=10,total<=45`.
+    #[clap(long, value_name = "LIMITS", value_delimiter = ',')]
+    assert: Vec<Limit>,
+
+    /// Read limits from a file, one per line or separated by commas.
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    budget: Option<PathBuf>,
+}
+
+impl BudgetArgs {
+    fn load(self) -> Result<Budget, ActionsError> {
+        let mut limits = self.assert;
+        if let Some(path) = self.budget {
+            let text =
+                fs::read_to_string(&path).map_err(|e| ActionsError::ReadBudget(path.clone(), e))?;
+            let budget = Budget::parse(&text).map_err(|e| ActionsError::ParseBudget(path, e))?;
+            limits.extend(budget.limits);
+        }
+        Ok(Budget { limits })
+    }
+}
+
+/// Saved sessions are totalled with the weights they were saved with, unless
+/// asked to use the configured ones.
+#[derive(Args, Debug)]
+struct ReweightArgs {
+    /// Total the sessions with the weights configured for their program
+    /// instead of the ones they were saved with.
+    #[clap(long)]
+    reweight: bool,
+
+    /// Take the weights from this profile instead of the one matching the program.
+    #[clap(long, value_name = "NAME", requires = "reweight")]
+    profile: Option<String>,
+}
+
+impl ReweightArgs {
+    fn apply(&self, config: &Config, summary: &mut Summary) -> Result<(), ActionsError> {
+        if self.reweight {
+            summary.weights = config
+                .weights_for(self.profile.as_deref(), &summary.program)
+                .map_err(ActionsError::Config)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+struct HistoryArgs {
+    #[command(subcommand)]
+    command: Option<HistoryCommand>,
+
+    #[command(flatten)]
+    reweight: ReweightArgs,
+
+    /// Path of the history database [default: $XDG_DATA_HOME/wl-actions/history.sqlite]
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    history_db: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommand {
+    /// List sessions, most recent first (the default).
+    List {
+        /// Only list sessions of this program.
+        #[clap(short, long)]
+        program: Option<String>,
+
+        /// How many sessions to list.
+        #[clap(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Show the summary of a session.
+    Show {
+        /// The session ID, as shown by `list`.
+        id: i64,
+    },
+}
+
 pub fn main() -> Result<(), ActionsError> {
     let args = WlActions::parse();
     if let Some(shell) = args.generate_completion {
@@ -35,5 +313,380 @@ pub fn main() -> Result<(), ActionsError> {
         clap_complete::generate(shell, &mut WlActions::command(), "wl-actions", &mut stdout);
         return Ok(());
     }
-    actions::main(args.quiet, args.program.unwrap())
+    let config = if args.no_config {
+        Config::default()
+    } else {
+        match (args.config, config::default_path()) {
+            (Some(path), _) => Config::load(&path, true),
+            (None, Some(path)) => Config::load(&path, false),
+            (None, None) => Ok(Config::default()),
+        }
+        .map_err(ActionsError::Config)?
+    };
+    let default_history_db = config.defaults.history_db.clone();
+    match args.command {
+        Some(Command::History(mut args)) => {
+            args.history_db = args.history_db.or(default_history_db);
+            return history_main(args, &config);
+        }
+        Some(Command::Compare {
+            a,
+            b,
+            reweight,
+            history_db,
+        }) => {
+            let history_db = history_db.or(default_history_db);
+            return compare_main(&a, &b, history_db, &reweight, &config);
+        }
+        Some(Command::Check {
+            session,
+            budget,
+            reweight,
+            history_db,
+        }) => {
+            let history_db = history_db.or(default_history_db);
+            return check_main(&session, budget, history_db, &reweight, &config);
+        }
+        Some(Command::Replay {
+            recording,
+            budget,
+            reweight,
+            summary_json,
+        }) => {
+            return replay_main(&recording, budget, summary_json, &reweight, &config);
+        }
+        None => {}
+    }
+
+    let program = args.program.unwrap();
+    let cli = Settings {
+        quiet: args.quiet.then_some(true),
+        dashboard: args.dashboard.then_some(true),
+        scroll_debounce_ms: args.scroll_debounce_ms,
+        count_repeats: args.count_repeats.then_some(true),
+        lap_names: (!args.lap_names.is_empty()).then_some(args.lap_names),
+        lap_hotkey: args.lap_hotkey,
+        pause_hotkey: args.pause_hotkey,
+        swallow_hotkeys: args.swallow_hotkeys.then_some(true),
+        filter: (!args.filter.is_empty()).then_some(args.filter),
+        status_bar: args.status_bar,
+        status_bar_output: args.status_bar_output,
+        metrics_listen: args.metrics_listen,
+        history: args.history.then_some(true),
+        history_db: args.history_db,
+        summary_json: args.summary_json,
+        assert: (!args.budget.assert.is_empty()).then_some(args.budget.assert),
+        budget: args.budget.budget,
+        weights: args.weights,
+        click_heatmap: args.click_heatmap,
+        click_csv: args.click_csv,
+        keyboard_heatmap: args.keyboard_heatmap,
+        keyboard_layout: args.keyboard_layout,
+        privacy: args.privacy,
+        ..Settings::default()
+    };
+    // Without a profile for the program, the app_id may still pick one
+    let matched = config
+        .profile(args.profile.as_deref(), &program[0])
+        .map_err(ActionsError::Config)?
+        .is_some();
+    let app_profiles = if matched {
+        Vec::new()
+    } else {
+        config
+            .app_profiles(&cli)
+            .into_iter()
+            .map(|(name, settings)| {
+                Ok(AppProfile {
+                    name: name.to_string(),
+                    hotkeys: hotkeys(&settings),
+                    scroll_debounce: scroll_debounce(&settings),
+                    count_repeats: settings.count_repeats.unwrap_or(false),
+                    privacy: settings.privacy.unwrap_or_default(),
+                    budget: BudgetArgs {
+                        assert: settings.assert.unwrap_or_default(),
+                        budget: settings.budget,
+                    }
+                    .load()?,
+                    app_ids: settings.app_ids,
+                    filter: settings.filter.unwrap_or_default(),
+                    weights: settings.weights.unwrap_or_default(),
+                })
+            })
+            .collect::<Result<_, ActionsError>>()?
+    };
+    let settings = config
+        .resolve(args.profile.as_deref(), &program[0])
+        .map_err(ActionsError::Config)?
+        .merge(cli);
+
+    let control_socket = match args.control_socket {
+        Some(path) => Some(path),
+        None if args.control => Some(socket::default_path().ok_or(ActionsError::NoRuntimeDir)?),
+        None => None,
+    };
+    let dashboard = settings.dashboard.unwrap_or(false);
+    if dashboard && !stderr().is_terminal() {
+        return Err(ActionsError::DashboardTerminal);
+    }
+    let privacy = settings.privacy.unwrap_or_default();
+    // A profile picked by app_id may raise the privacy level later on
+    let restricted = app_profiles
+        .iter()
+        .map(|profile| profile.privacy)
+        .chain([privacy])
+        .find(|privacy| *privacy != Privacy::Off);
+    if let Some(privacy) = restricted {
+        if settings.keyboard_heatmap.is_some() {
+            return Err(ActionsError::HeatmapPrivacy(privacy));
+        }
+        if args.record.is_some() {
+            return Err(ActionsError::RecordPrivacy(privacy));
+        }
+    }
+    let hotkeys = hotkeys(&settings);
+    let scroll_debounce = scroll_debounce(&settings);
+    let budget = BudgetArgs {
+        assert: settings.assert.unwrap_or_default(),
+        budget: settings.budget,
+    };
+    let options = Options {
+        quiet: settings.quiet.unwrap_or(false),
+        verbose: args.verbose,
+        dashboard,
+        lap_names: settings.lap_names.unwrap_or_default(),
+        hotkeys,
+        app_profiles,
+        filter: settings.filter.unwrap_or_default(),
+        control_socket,
+        status_bar: settings.status_bar,
+        status_bar_output: settings.status_bar_output,
+        metrics_listen: settings.metrics_listen,
+        history_db: settings
+            .history
+            .unwrap_or(false)
+            .then(|| settings.history_db.unwrap_or_else(history::default_path)),
+        summary_json: settings.summary_json,
+        record: args.record,
+        budget: budget.load()?,
+        scroll_debounce,
+        weights: settings.weights.unwrap_or_default(),
+        count_repeats: settings.count_repeats.unwrap_or(false),
+        click_heatmap: settings.click_heatmap,
+        click_csv: settings.click_csv,
+        keyboard_heatmap: settings.keyboard_heatmap,
+        keyboard_layout: settings.keyboard_layout.unwrap_or_default(),
+        privacy,
+    };
+    actions::main(options, program)
+}
+
+fn scroll_debounce(settings: &Settings) -> Duration {
+    settings
+        .scroll_debounce_ms
+        .map_or(DEFAULT_SCROLL_DEBOUNCE, Duration::from_millis)
+}
+
+fn hotkeys(settings: &Settings) -> Hotkeys {
+    Hotkeys {
+        lap: settings.lap_hotkey,
+        pause: settings.pause_hotkey,
+        swallow: settings.swallow_hotkeys.unwrap_or(false),
+    }
+}
+
+fn history_main(args: HistoryArgs, config: &Config) -> Result<(), ActionsError> {
+    let path = args.history_db.unwrap_or_else(history::default_path);
+    let history = History::open(&path).map_err(ActionsError::History)?;
+    let stdout = stdout();
+    let mut stdout = stdout.lock();
+    match args.command.unwrap_or(HistoryCommand::List {
+        program: None,
+        limit: 20,
+    }) {
+        HistoryCommand::List { program, limit } => {
+            let mut entries = history
+                .list(program.as_deref(), limit)
+                .map_err(ActionsError::History)?;
+            for entry in &mut entries {
+                args.reweight.apply(config, &mut entry.summary)?;
+            }
+            history::write_list(&mut stdout, &entries).map_err(ActionsError::Stdout)
+        }
+        HistoryCommand::Show { id } => match history.get(id).map_err(ActionsError::History)? {
+            Some(mut entry) => {
+                args.reweight.apply(config, &mut entry.summary)?;
+                history::write_entry(&mut stdout, &entry).map_err(ActionsError::Stdout)
+            }
+            None => Err(ActionsError::NoSuchSession(id)),
+        },
+    }
+}
+
+fn compare_main(
+    a: &str,
+    b: &str,
+    history_db: Option<PathBuf>,
+    reweight: &ReweightArgs,
+    config: &Config,
+) -> Result<(), ActionsError> {
+    let history_db = history_db.unwrap_or_else(history::default_path);
+    let (a_label, mut a) = load_summary(a, &history_db)?;
+    let (b_label, mut b) = load_summary(b, &history_db)?;
+    reweight.apply(config, &mut a)?;
+    reweight.apply(config, &mut b)?;
+    let stdout = stdout();
+    let mut stdout = stdout.lock();
+    compare::write(&mut stdout, &a_label, &b_label, &compare::compare(&a, &b))
+        .map_err(ActionsError::Stdout)
+}
+
+fn check_main(
+    session: &str,
+    budget: BudgetArgs,
+    history_db: Option<PathBuf>,
+    reweight: &ReweightArgs,
+    config: &Config,
+) -> Result<(), ActionsError> {
+    let budget = budget.load()?;
+    if budget.is_empty() {
+        return Err(ActionsError::EmptyBudget);
+    }
+    let history_db = history_db.unwrap_or_else(history::default_path);
+    let (label, mut summary) = load_summary(session, &history_db)?;
+    reweight.apply(config, &mut summary)?;
+    let checks = budget.check(&summary);
+    let stdout = stdout();
+    let mut stdout = stdout.lock();
+    writeln!(stdout, "Session: {}", label).map_err(ActionsError::Stdout)?;
+    budget::write(&mut stdout, &checks).map_err(ActionsError::Stdout)?;
+    if !checks.iter().all(Check::passed) {
+        exit(budget::EXIT_BUDGET_EXCEEDED);
+    }
+    Ok(())
+}
+
+fn replay_main(
+    recording: &Path,
+    budget: BudgetArgs,
+    summary_json: Option<PathBuf>,
+    reweight: &ReweightArgs,
+    config: &Config,
+) -> Result<(), ActionsError> {
+    let budget = budget.load()?;
+    let file =
+        File::open(recording).map_err(|e| ActionsError::OpenRecording(recording.to_owned(), e))?;
+    let mut summary = recording::replay(BufReader::new(file), detector::builtin())
+        .map_err(|e| ActionsError::Replay(recording.to_owned(), e))?;
+    reweight.apply(config, &mut summary)?;
+    if let Some(path) = summary_json {
+        let json = serde_json::to_string_pretty(&summary).unwrap();
+        fs::write(&path, json + "\n").map_err(|e| ActionsError::WriteSummary(path, e))?;
+    }
+    let stdout = stdout();
+    let mut stdout = stdout.lock();
+    summary::write(&mut stdout, &summary).map_err(ActionsError::Stdout)?;
+    let checks = budget.check(&summary);
+    if !checks.is_empty() {
+        budget::write(&mut stdout, &checks).map_err(ActionsError::Stdout)?;
+    }
+    if !checks.iter().all(Check::passed) {
+        exit(budget::EXIT_BUDGET_EXCEEDED);
+    }
+    Ok(())
+}
+
+/// Loads a summary from a JSON file or, if there is no such file and the
+/// argument is a number, from the history.
+fn load_summary(arg: &str, history_db: &Path) -> Result<(String, Summary), ActionsError> {
+    let path = Path::new(arg);
+    if !path.exists()
+        && let Ok(id) = arg.trim_start_matches('#').parse::<i64>()
+    {
+        let history = History::open(history_db).map_err(ActionsError::History)?;
+        let entry = history
+            .get(id)
+            .map_err(ActionsError::History)?
+            .ok_or(ActionsError::NoSuchSession(id))?;
+        let summary = entry.summary;
+        let label = format!("session {} ({})", id, summary.program.join(" "));
+        return Ok((label, summary));
+    }
+    let json =
+        fs::read_to_string(path).map_err(|e| ActionsError::ReadSummary(path.to_owned(), e))?;
+    let summary: Summary =
+        serde_json::from_str(&json).map_err(|e| ActionsError::ParseSummary(path.to_owned(), e))?;
+    Ok((arg.to_string(), summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli() {
+        WlActions::command().debug_assert();
+
+        let args = WlActions::try_parse_from(["wl-actions", "-q", "firefox", "-P", "x"]).unwrap();
+        assert!(args.command.is_none());
+        assert_eq!(args.program.unwrap(), ["firefox", "-P", "x"]);
+
+        let args = WlActions::try_parse_from(["wl-actions", "history", "show", "3"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::History(HistoryArgs {
+                command: Some(HistoryCommand::Show { id: 3 }),
+                ..
+            }))
+        ));
+
+        // `--` runs a program that happens to be called like a subcommand
+        let args = WlActions::try_parse_from(["wl-actions", "--", "history"]).unwrap();
+        assert!(args.command.is_none());
+        assert_eq!(args.program.unwrap(), ["history"]);
+
+        let args = WlActions::try_parse_from([
+            "wl-actions",
+            "--assert",
+            "keys<=40,clicks<=10",
+            "--assert",
+            "total<=45",
+            "firefox",
+        ])
+        .unwrap();
+        assert_eq!(args.budget.assert.len(), 3);
+
+        let args =
+            WlActions::try_parse_from(["wl-actions", "compare", "1", "2", "--reweight"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Compare {
+                reweight: ReweightArgs {
+                    reweight: true,
+                    profile: None
+                },
+                ..
+            })
+        ));
+        // A profile only picks the weights for --reweight
+        assert!(
+            WlActions::try_parse_from(["wl-actions", "compare", "1", "2", "--profile", "x"])
+                .is_err()
+        );
+        assert!(WlActions::try_parse_from(["wl-actions", "--assert", "mouse<=1", "x"]).is_err());
+
+        let args = WlActions::try_parse_from([
+            "wl-actions",
+            "replay",
+            "login.jsonl",
+            "--assert",
+            "clicks<=10",
+        ])
+        .unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Replay { ref recording, ref budget, .. })
+                if recording == Path::new("login.jsonl") && budget.assert.len() == 1
+        ));
+    }
 }
@@ -0,0 +1,346 @@
+//! Click and tap positions per surface, exported as an SVG heatmap and CSV.
+
+use {
+    std::{
+        collections::HashMap,
+        io::{self, Write},
+        time::Duration,
+    },
+    wl_actions::session::CounterSnapshot,
+};
+
+/// Identifies a surface for as long as it exists.
+pub type SurfaceKey = usize;
+
+/// Heatmap panels are scaled down to at most this width.
+const PANEL_WIDTH: f64 = 640.0;
+const HEAT_RADIUS: f64 = 24.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClickKind {
+    /// A pointer button press, with the evdev button code.
+    Click(u32),
+    Tap,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Click {
+    /// Index into the recorded surfaces.
+    pub surface: usize,
+    /// Surface-local coordinates.
+    pub x: f64,
+    pub y: f64,
+    pub kind: ClickKind,
+    /// Active session time of the click.
+    pub at: Duration,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SurfaceInfo {
+    pub title: Option<String>,
+    pub app_id: Option<String>,
+    /// The latest non-zero size from `xdg_toplevel.configure`.
+    pub size: Option<(i32, i32)>,
+    /// The window geometry from `xdg_surface.set_window_geometry`, which
+    /// excludes client-side shadows.
+    pub geometry: Option<(i32, i32, i32, i32)>,
+    /// The actions that went to the surface.
+    pub counts: CounterSnapshot,
+}
+
+#[derive(Debug, Default)]
+pub struct ClickRecorder {
+    surfaces: Vec<SurfaceInfo>,
+    index: HashMap<SurfaceKey, usize>,
+    clicks: Vec<Click>,
+}
+
+impl ClickRecorder {
+    fn surface_index(&mut self, key: SurfaceKey) -> usize {
+        *self.index.entry(key).or_insert_with(|| {
+            self.surfaces.push(SurfaceInfo::default());
+            self.surfaces.len() - 1
+        })
+    }
+
+    fn surface(&mut self, key: SurfaceKey) -> &mut SurfaceInfo {
+        let index = self.surface_index(key);
+        &mut self.surfaces[index]
+    }
+
+    pub fn set_size(&mut self, key: SurfaceKey, width: i32, height: i32) {
+        if width > 0 && height > 0 {
+            self.surface(key).size = Some((width, height));
+        }
+    }
+
+    pub fn set_geometry(&mut self, key: SurfaceKey, x: i32, y: i32, width: i32, height: i32) {
+        if width > 0 && height > 0 {
+            self.surface(key).geometry = Some((x, y, width, height));
+        }
+    }
+
+    pub fn set_title(&mut self, key: SurfaceKey, title: &str) {
+        self.surface(key).title = Some(title.to_string());
+    }
+
+    pub fn set_app_id(&mut self, key: SurfaceKey, app_id: &str) {
+        self.surface(key).app_id = Some(app_id.to_string());
+    }
+
+    /// The surface is gone; a new surface may reuse its key.
+    pub fn forget(&mut self, key: SurfaceKey) {
+        self.index.remove(&key);
+    }
+
+    pub fn record(&mut self, key: SurfaceKey, x: f64, y: f64, kind: ClickKind, at: Duration) {
+        let surface = self.surface_index(key);
+        self.clicks.push(Click {
+            surface,
+            x,
+            y,
+            kind,
+            at,
+        });
+    }
+
+    /// Adds actions that went to a surface.
+    pub fn count(&mut self, key: SurfaceKey, counts: CounterSnapshot) {
+        self.surface(key).counts += counts;
+    }
+
+    /// The surfaces that got any actions, by label, with their counts.
+    pub fn windows(&self) -> Vec<(String, CounterSnapshot)> {
+        (0..self.surfaces.len())
+            .filter(|&surface| self.surfaces[surface].counts != CounterSnapshot::default())
+            .map(|surface| (self.label(surface), self.surfaces[surface].counts))
+            .collect()
+    }
+
+    /// Drops the clicks and counts but remembers the surfaces.
+    pub fn reset(&mut self) {
+        self.clicks.clear();
+        for surface in &mut self.surfaces {
+            surface.counts = CounterSnapshot::default();
+        }
+    }
+
+    fn label(&self, surface: usize) -> String {
+        let info = &self.surfaces[surface];
+        info.title
+            .clone()
+            .or_else(|| info.app_id.clone())
+            .unwrap_or_else(|| format!("surface {}", surface + 1))
+    }
+
+    /// The area of a surface to draw: the window geometry, the configured
+    /// size or else just enough to hold the clicks.
+    fn bounds(&self, surface: usize) -> (f64, f64, f64, f64) {
+        let info = &self.surfaces[surface];
+        if let Some((x, y, width, height)) = info.geometry {
+            return (x as f64, y as f64, width as f64, height as f64);
+        }
+        if let Some((width, height)) = info.size {
+            return (0.0, 0.0, width as f64, height as f64);
+        }
+        let (width, height) = self
+            .clicks
+            .iter()
+            .filter(|click| click.surface == surface)
+            .fold((1.0f64, 1.0f64), |(w, h), click| {
+                (w.max(click.x + HEAT_RADIUS), h.max(click.y + HEAT_RADIUS))
+            });
+        (0.0, 0.0, width, height)
+    }
+}
+
+pub fn write_csv(out: &mut impl Write, recorder: &ClickRecorder) -> io::Result<()> {
+    writeln!(out, "surface,label,kind,button,x,y,seconds")?;
+    for click in &recorder.clicks {
+        let (kind, button) = match click.kind {
+            ClickKind::Click(button) => ("click", button.to_string()),
+            ClickKind::Tap => ("tap", String::new()),
+        };
+        writeln!(
+            out,
+            "{},{},{},{},{:.1},{:.1},{:.3}",
+            click.surface + 1,
+            csv_field(&recorder.label(click.surface)),
+            kind,
+            button,
+            click.x,
+            click.y,
+            click.at.as_secs_f64()
+        )?;
+    }
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Draws one panel per surface that was clicked, with a translucent blob per
+/// click so that frequently clicked areas get darker.
+pub fn write_svg(out: &mut impl Write, recorder: &ClickRecorder) -> io::Result<()> {
+    let mut surfaces: Vec<usize> = recorder.clicks.iter().map(|click| click.surface).collect();
+    surfaces.sort_unstable();
+    surfaces.dedup();
+
+    let panels: Vec<_> = surfaces
+        .iter()
+        .map(|&surface| {
+            let (x, y, width, height) = recorder.bounds(surface);
+            let scale = (PANEL_WIDTH / width).min(1.0);
+            (surface, x, y, width, height, scale)
+        })
+        .collect();
+    let total_height: f64 = panels
+        .iter()
+        .map(|&(_, _, _, _, height, scale)| height * scale + 40.0)
+        .sum();
+
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="14">"#,
+        w = PANEL_WIDTH + 20.0,
+        h = total_height.max(40.0)
+    )?;
+    writeln!(
+        out,
+        r##"<defs><radialGradient id="heat"><stop offset="0" stop-color="#e8290b" stop-opacity="0.55"/><stop offset="1" stop-color="#e8290b" stop-opacity="0"/></radialGradient></defs>"##
+    )?;
+    let mut top = 0.0;
+    for (surface, x, y, width, height, scale) in panels {
+        let clicks: Vec<_> = recorder
+            .clicks
+            .iter()
+            .filter(|click| click.surface == surface)
+            .collect();
+        writeln!(
+            out,
+            r#"<text x="10" y="{:.1}">{} ({}×{}, {} clicks)</text>"#,
+            top + 20.0,
+            xml_escape(&recorder.label(surface)),
+            width,
+            height,
+            clicks.len()
+        )?;
+        let origin = top + 30.0;
+        writeln!(
+            out,
+            r##"<rect x="10" y="{:.1}" width="{:.1}" height="{:.1}" fill="#f5f5f5" stroke="#999"/>"##,
+            origin,
+            width * scale,
+            height * scale
+        )?;
+        for click in &clicks {
+            writeln!(
+                out,
+                r#"<circle cx="{:.1}" cy="{:.1}" r="{}" fill="url(#heat)"/>"#,
+                10.0 + (click.x - x) * scale,
+                origin + (click.y - y) * scale,
+                HEAT_RADIUS
+            )?;
+        }
+        for click in &clicks {
+            writeln!(
+                out,
+                r#"<circle cx="{:.1}" cy="{:.1}" r="1.5"/>"#,
+                10.0 + (click.x - x) * scale,
+                origin + (click.y - y) * scale
+            )?;
+        }
+        top = origin + height * scale + 10.0;
+    }
+    writeln!(out, "</svg>")
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorder() -> ClickRecorder {
+        let mut recorder = ClickRecorder::default();
+        recorder.set_size(1, 1280, 720);
+        recorder.set_title(1, "Checkout, step 2");
+        recorder.record(
+            1,
+            100.0,
+            50.0,
+            ClickKind::Click(0x110),
+            Duration::from_secs(1),
+        );
+        recorder.record(2, 10.0, 20.0, ClickKind::Tap, Duration::from_millis(2500));
+        recorder
+    }
+
+    #[test]
+    fn test_window_counts() {
+        let mut recorder = recorder();
+        let clicks = CounterSnapshot {
+            button_clicks: 1,
+            ..CounterSnapshot::default()
+        };
+        recorder.count(1, clicks);
+        recorder.count(1, clicks);
+        assert_eq!(
+            recorder.windows(),
+            [(
+                "Checkout, step 2".to_string(),
+                CounterSnapshot {
+                    button_clicks: 2,
+                    ..CounterSnapshot::default()
+                }
+            )]
+        );
+        recorder.reset();
+        assert!(recorder.windows().is_empty());
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let mut out = Vec::new();
+        write_csv(&mut out, &recorder()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "surface,label,kind,button,x,y,seconds\n\
+             1,\"Checkout, step 2\",click,272,100.0,50.0,1.000\n\
+             2,surface 2,tap,,10.0,20.0,2.500\n"
+        );
+    }
+
+    #[test]
+    fn test_write_svg() {
+        let mut out = Vec::new();
+        write_svg(&mut out, &recorder()).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("Checkout, step 2 (1280×720, 1 clicks)"));
+        // Scaled from 1280 to 640 pixels wide
+        assert!(svg.contains(r#"<circle cx="60.0" cy="55.0" r="24" fill="url(#heat)"/>"#));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_reused_surface_key() {
+        let mut recorder = recorder();
+        recorder.forget(1);
+        recorder.record(1, 0.0, 0.0, ClickKind::Tap, Duration::ZERO);
+        assert_eq!(recorder.clicks[2].surface, 2);
+        recorder.reset();
+        assert!(recorder.clicks.is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::{Duration, Instant};
+
+/// Measures session time, excluding the time spent paused.
+#[derive(Clone, Debug)]
+pub struct SessionClock {
+    start: Instant,
+    paused_at: Option<Instant>,
+    paused_total: Duration,
+}
+
+impl SessionClock {
+    pub fn new(start: Instant) -> Self {
+        Self {
+            start,
+            paused_at: None,
+            paused_total: Duration::ZERO,
+        }
+    }
+
+    /// Restarts the clock at `now`, keeping the paused state.
+    pub fn reset(&mut self, now: Instant) {
+        self.start = now;
+        self.paused_total = Duration::ZERO;
+        if self.paused_at.is_some() {
+            self.paused_at = Some(now);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Returns false if the clock was already paused.
+    pub fn pause(&mut self, now: Instant) -> bool {
+        if self.is_paused() {
+            return false;
+        }
+        self.paused_at = Some(now);
+        true
+    }
+
+    /// Returns false if the clock was not paused.
+    pub fn resume(&mut self, now: Instant) -> bool {
+        let Some(paused_at) = self.paused_at.take() else {
+            return false;
+        };
+        self.paused_total += now.saturating_duration_since(paused_at);
+        true
+    }
+
+    /// Time spent paused up to `now`, including a pause still in progress.
+    pub fn paused_time(&self, now: Instant) -> Duration {
+        let current = self
+            .paused_at
+            .map(|paused_at| now.saturating_duration_since(paused_at))
+            .unwrap_or_default();
+        self.paused_total + current
+    }
+
+    /// Time spent counting up to `now`.
+    pub fn active_time(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.start)
+            .saturating_sub(self.paused_time(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paused_time_excluded() {
+        let start = Instant::now();
+        let secs = |n| start + Duration::from_secs(n);
+        let mut clock = SessionClock::new(start);
+
+        assert_eq!(clock.active_time(secs(10)), Duration::from_secs(10));
+
+        assert!(clock.pause(secs(10)));
+        assert!(!clock.pause(secs(12)));
+        assert!(clock.is_paused());
+        assert_eq!(clock.active_time(secs(15)), Duration::from_secs(10));
+        assert_eq!(clock.paused_time(secs(15)), Duration::from_secs(5));
+
+        assert!(clock.resume(secs(20)));
+        assert!(!clock.resume(secs(21)));
+        assert_eq!(clock.active_time(secs(25)), Duration::from_secs(15));
+        assert_eq!(clock.paused_time(secs(25)), Duration::from_secs(10));
+
+        clock.reset(secs(30));
+        assert_eq!(clock.active_time(secs(32)), Duration::from_secs(2));
+        assert_eq!(clock.paused_time(secs(32)), Duration::ZERO);
+    }
+}
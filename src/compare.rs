@@ -0,0 +1,342 @@
+//! Side-by-side comparison of two sessions.
+
+use {
+    std::io::{self, Write},
+    wl_actions::{
+        laps::lap_label,
+        session::CounterSnapshot,
+        summary::{LapSummary, Summary},
+        weights::Weights,
+    },
+};
+
+/// One compared value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    pub label: &'static str,
+    pub a: f64,
+    pub b: f64,
+}
+
+impl Row {
+    pub fn delta(&self) -> f64 {
+        self.b - self.a
+    }
+
+    /// The change from `a` to `b` in percent, `None` if `a` is zero.
+    pub fn change(&self) -> Option<f64> {
+        (self.a != 0.0).then(|| self.delta() / self.a * 100.0)
+    }
+}
+
+/// The laps of both sessions with the same name, or at the same position if
+/// they are unnamed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LapComparison {
+    pub label: String,
+    pub a: Option<LapSummary>,
+    pub b: Option<LapSummary>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    pub rows: Vec<Row>,
+    pub laps: Vec<LapComparison>,
+    /// The weights of A, which both sessions are totalled with.
+    pub weights: Weights,
+    /// Whether B was saved with other weights than A.
+    pub other_weights: bool,
+}
+
+pub fn compare(a: &Summary, b: &Summary) -> Comparison {
+    let weights = a.weights;
+    let per_minute = |summary: &Summary| {
+        if summary.duration_secs > 0.0 {
+            weights.total(&summary.counters) / summary.duration_secs * 60.0
+        } else {
+            0.0
+        }
+    };
+    let mut rows = counter_rows(&a.counters, &b.counters);
+    rows.push(Row {
+        label: "Total actions",
+        a: weights.total(&a.counters),
+        b: weights.total(&b.counters),
+    });
+    rows.push(Row {
+        label: "Modality switches",
+        a: a.modalities.switches as f64,
+        b: b.modalities.switches as f64,
+    });
+    rows.push(Row {
+        label: "Duration (s)",
+        a: a.duration_secs,
+        b: b.duration_secs,
+    });
+    rows.push(Row {
+        label: "Actions per minute",
+        a: per_minute(a),
+        b: per_minute(b),
+    });
+    Comparison {
+        rows,
+        laps: match_laps(&a.laps, &b.laps),
+        weights,
+        other_weights: b.weights != weights,
+    }
+}
+
+fn counter_rows(a: &CounterSnapshot, b: &CounterSnapshot) -> Vec<Row> {
+    vec![
+        Row {
+            label: "Key presses",
+            a: a.key_presses as f64,
+            b: b.key_presses as f64,
+        },
+        Row {
+            label: "Button clicks",
+            a: a.button_clicks as f64,
+            b: b.button_clicks as f64,
+        },
+        Row {
+            label: "Scroll steps",
+            a: a.scroll_steps as f64,
+            b: b.scroll_steps as f64,
+        },
+        Row {
+            label: "Touch taps",
+            a: a.touch_taps as f64,
+            b: b.touch_taps as f64,
+        },
+    ]
+}
+
+fn match_laps(a: &[LapSummary], b: &[LapSummary]) -> Vec<LapComparison> {
+    let named = |laps: &[LapSummary]| laps.iter().all(|lap| lap.name.is_some());
+    let mut laps = Vec::new();
+    if named(a) && named(b) {
+        for lap in a {
+            laps.push(LapComparison {
+                label: lap.name.clone().unwrap(),
+                a: Some(lap.clone()),
+                b: b.iter().find(|other| other.name == lap.name).cloned(),
+            });
+        }
+        for lap in b {
+            if !a.iter().any(|other| other.name == lap.name) {
+                laps.push(LapComparison {
+                    label: lap.name.clone().unwrap(),
+                    a: None,
+                    b: Some(lap.clone()),
+                });
+            }
+        }
+    } else {
+        for i in 0..a.len().max(b.len()) {
+            let (a, b) = (a.get(i).cloned(), b.get(i).cloned());
+            let name = a
+                .as_ref()
+                .or(b.as_ref())
+                .and_then(|lap| lap.name.as_deref());
+            laps.push(LapComparison {
+                label: format!("Lap {}{}", i + 1, lap_label(name)),
+                a,
+                b,
+            });
+        }
+    }
+    laps
+}
+
+pub fn write(out: &mut impl Write, a: &str, b: &str, comparison: &Comparison) -> io::Result<()> {
+    writeln!(out, "A: {}", a)?;
+    writeln!(out, "B: {}", b)?;
+    if comparison.other_weights {
+        writeln!(
+            out,
+            "B was saved with other weights, both are totalled with those of A"
+        )?;
+    }
+    writeln!(out)?;
+    write_rows(out, &comparison.rows)?;
+
+    for lap in &comparison.laps {
+        writeln!(out, "\n=== {} ===", lap.label)?;
+        match (&lap.a, &lap.b) {
+            (Some(a), Some(b)) => {
+                let mut rows = counter_rows(&a.counters, &b.counters);
+                rows.push(Row {
+                    label: "Total actions",
+                    a: comparison.weights.total(&a.counters),
+                    b: comparison.weights.total(&b.counters),
+                });
+                rows.push(Row {
+                    label: "Duration (s)",
+                    a: a.duration_secs,
+                    b: b.duration_secs,
+                });
+                write_rows(out, &rows)?;
+            }
+            (Some(_), None) => writeln!(out, "Only in A")?,
+            (None, _) => writeln!(out, "Only in B")?,
+        }
+    }
+
+    let headlines: Vec<_> = comparison.rows.iter().filter_map(headline).collect();
+    if !headlines.is_empty() {
+        writeln!(out)?;
+        for line in headlines {
+            writeln!(out, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_rows(out: &mut impl Write, rows: &[Row]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:<20} {:>10} {:>10} {:>10} {:>9}",
+        "", "A", "B", "Delta", "Change"
+    )?;
+    for row in rows {
+        let change = match row.change() {
+            Some(change) => format!("{:+.1}%", change),
+            None => "-".to_string(),
+        };
+        writeln!(
+            out,
+            "{:<20} {:>10} {:>10} {:>10} {:>9}",
+            row.label,
+            format_value(row.a),
+            format_value(row.b),
+            format_delta(row.delta()),
+            change
+        )?;
+    }
+    Ok(())
+}
+
+/// A sentence such as "B needs 23.1% fewer button clicks than A".
+fn headline(row: &Row) -> Option<String> {
+    let change = row.change()?;
+    if change.abs() < 0.05 {
+        return None;
+    }
+    let label = row.label.to_lowercase();
+    let sentence = match row.label {
+        "Duration (s)" => format!(
+            "B takes {:.1}% {} than A",
+            change.abs(),
+            if change < 0.0 {
+                "less time"
+            } else {
+                "more time"
+            }
+        ),
+        "Actions per minute" => format!(
+            "B has a {:.1}% {} action rate than A",
+            change.abs(),
+            if change < 0.0 { "lower" } else { "higher" }
+        ),
+        _ => format!(
+            "B needs {:.1}% {} {} than A",
+            change.abs(),
+            if change < 0.0 { "fewer" } else { "more" },
+            label
+        ),
+    };
+    Some(sentence)
+}
+
+fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value)
+    } else {
+        format!("{:.1}", value)
+    }
+}
+
+fn format_delta(delta: f64) -> String {
+    if delta.fract() == 0.0 {
+        format!("{:+}", delta)
+    } else {
+        format!("{:+.1}", delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::fixtures::{self, counters},
+    };
+
+    fn summary(keys: u64, clicks: u64, laps: Vec<LapSummary>) -> Summary {
+        Summary {
+            laps,
+            ..fixtures::summary("firefox", 0, keys, clicks)
+        }
+    }
+
+    fn lap(name: Option<&str>, keys: u64) -> LapSummary {
+        LapSummary {
+            name: name.map(str::to_string),
+            duration_secs: 10.0,
+            counters: counters(keys, 0),
+        }
+    }
+
+    #[test]
+    fn test_compare_counters() {
+        let comparison = compare(&summary(40, 13, Vec::new()), &summary(40, 10, Vec::new()));
+        let clicks = &comparison.rows[1];
+        assert_eq!(clicks.label, "Button clicks");
+        assert_eq!(clicks.delta(), -3.0);
+        assert!((clicks.change().unwrap() + 23.08).abs() < 0.01);
+        assert_eq!(
+            headline(clicks).unwrap(),
+            "B needs 23.1% fewer button clicks than A"
+        );
+        // No change, no headline
+        assert_eq!(headline(&comparison.rows[0]), None);
+        // Nothing to compare against
+        assert_eq!(comparison.rows[3].change(), None);
+    }
+
+    #[test]
+    fn test_compare_with_the_weights_of_a() {
+        let a = summary(40, 10, vec![lap(None, 4)]);
+        let mut b = summary(40, 10, vec![lap(None, 4)]);
+        b.weights.keys = 2.0;
+        let comparison = compare(&a, &b);
+        assert!(comparison.other_weights);
+        let total = &comparison.rows[4];
+        assert_eq!(total.label, "Total actions");
+        assert_eq!((total.a, total.b), (50.0, 50.0));
+        assert_eq!(comparison.rows[7].delta(), 0.0);
+
+        let mut out = Vec::new();
+        write(&mut out, "a.json", "b.json", &comparison).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("both are totalled with those of A"));
+        assert!(!out.contains("B needs"));
+    }
+
+    #[test]
+    fn test_match_laps() {
+        let a = [lap(Some("login"), 5), lap(Some("search"), 3)];
+        let b = [lap(Some("search"), 2), lap(Some("checkout"), 1)];
+        let laps = match_laps(&a, &b);
+        let labels: Vec<_> = laps.iter().map(|lap| lap.label.as_str()).collect();
+        assert_eq!(labels, ["login", "search", "checkout"]);
+        assert!(laps[0].b.is_none());
+        assert_eq!(laps[1].b.as_ref().unwrap().counters.key_presses, 2);
+        assert!(laps[2].a.is_none());
+
+        // Unnamed laps are matched by position
+        let laps = match_laps(&[lap(None, 1), lap(None, 2)], &[lap(None, 3)]);
+        assert_eq!(laps.len(), 2);
+        assert_eq!(laps[0].label, "Lap 1");
+        assert!(laps[1].b.is_none());
+    }
+}
@@ -0,0 +1,364 @@
+//! The configuration file: defaults plus per-program profiles.
+
+use {
+    crate::{
+        budget::Limit, filter::Rule, hotkey::Hotkey, keyboard::KeyboardLayout,
+        metrics::program_label, status_bar::StatusBarFormat,
+    },
+    serde::Deserialize,
+    std::{
+        collections::BTreeMap,
+        env, fs, io,
+        net::SocketAddr,
+        path::{Path, PathBuf},
+    },
+    thiserror::Error,
+    wl_actions::privacy::Privacy,
+    wl_actions::weights::Weights,
+};
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("could not read {0}")]
+    Read(PathBuf, #[source] io::Error),
+    #[error("could not parse {0}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+    #[error("there is no profile `{0}` in the configuration")]
+    UnknownProfile(String),
+    #[error("profile `{0}` has app-ids, so it cannot set `{1}`, which only applies at startup")]
+    StartupKey(String, &'static str),
+}
+
+/// `$XDG_CONFIG_HOME/wl-actions/config.toml`, if there is a home directory.
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("wl-actions").join("config.toml"))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub defaults: Settings,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Settings>,
+}
+
+/// Everything that can be set in the configuration. The keys are named like
+/// the command line flags, which override them.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    /// Program names that select this profile besides the profile's own name.
+    #[serde(default)]
+    pub programs: Vec<String>,
+    /// App IDs that select this profile once the application sets one on a
+    /// window, if no profile matched the program.
+    #[serde(default)]
+    pub app_ids: Vec<String>,
+    pub quiet: Option<bool>,
+    pub dashboard: Option<bool>,
+    /// Scroll events closer together than this count as one step.
+    pub scroll_debounce_ms: Option<u64>,
+    /// Whether key repeats count as key presses.
+    pub count_repeats: Option<bool>,
+    pub lap_names: Option<Vec<String>>,
+    pub lap_hotkey: Option<Hotkey>,
+    pub pause_hotkey: Option<Hotkey>,
+    pub swallow_hotkeys: Option<bool>,
+    /// Rules for dropping, remapping and rate-limiting keys and buttons.
+    pub filter: Option<Vec<Rule>>,
+    pub status_bar: Option<StatusBarFormat>,
+    pub status_bar_output: Option<PathBuf>,
+    pub metrics_listen: Option<SocketAddr>,
+    pub history: Option<bool>,
+    pub history_db: Option<PathBuf>,
+    pub summary_json: Option<PathBuf>,
+    pub assert: Option<Vec<Limit>>,
+    pub budget: Option<PathBuf>,
+    /// How much each kind of action counts towards the total.
+    pub weights: Option<Weights>,
+    pub click_heatmap: Option<PathBuf>,
+    pub click_csv: Option<PathBuf>,
+    pub keyboard_heatmap: Option<PathBuf>,
+    pub keyboard_layout: Option<KeyboardLayout>,
+    /// How much key identity detailed output may keep.
+    pub privacy: Option<Privacy>,
+}
+
+impl Settings {
+    /// The keys set here that are only read at startup, so a profile
+    /// selected by app_id mid-session cannot set them.
+    fn startup_keys(&self) -> impl Iterator<Item = &'static str> {
+        [
+            ("quiet", self.quiet.is_some()),
+            ("dashboard", self.dashboard.is_some()),
+            ("lap-names", self.lap_names.is_some()),
+            ("status-bar", self.status_bar.is_some()),
+            ("status-bar-output", self.status_bar_output.is_some()),
+            ("metrics-listen", self.metrics_listen.is_some()),
+            ("history", self.history.is_some()),
+            ("history-db", self.history_db.is_some()),
+            ("summary-json", self.summary_json.is_some()),
+            ("click-heatmap", self.click_heatmap.is_some()),
+            ("click-csv", self.click_csv.is_some()),
+            ("keyboard-heatmap", self.keyboard_heatmap.is_some()),
+            ("keyboard-layout", self.keyboard_layout.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(key, _)| key)
+    }
+
+    /// Returns these settings with everything set in `other` replaced.
+    pub fn merge(self, other: Settings) -> Settings {
+        Settings {
+            programs: self.programs,
+            app_ids: self.app_ids,
+            quiet: other.quiet.or(self.quiet),
+            dashboard: other.dashboard.or(self.dashboard),
+            scroll_debounce_ms: other.scroll_debounce_ms.or(self.scroll_debounce_ms),
+            count_repeats: other.count_repeats.or(self.count_repeats),
+            lap_names: other.lap_names.or(self.lap_names),
+            lap_hotkey: other.lap_hotkey.or(self.lap_hotkey),
+            pause_hotkey: other.pause_hotkey.or(self.pause_hotkey),
+            swallow_hotkeys: other.swallow_hotkeys.or(self.swallow_hotkeys),
+            filter: other.filter.or(self.filter),
+            status_bar: other.status_bar.or(self.status_bar),
+            status_bar_output: other.status_bar_output.or(self.status_bar_output),
+            metrics_listen: other.metrics_listen.or(self.metrics_listen),
+            history: other.history.or(self.history),
+            history_db: other.history_db.or(self.history_db),
+            summary_json: other.summary_json.or(self.summary_json),
+            assert: other.assert.or(self.assert),
+            budget: other.budget.or(self.budget),
+            weights: other.weights.or(self.weights),
+            click_heatmap: other.click_heatmap.or(self.click_heatmap),
+            click_csv: other.click_csv.or(self.click_csv),
+            keyboard_heatmap: other.keyboard_heatmap.or(self.keyboard_heatmap),
+            keyboard_layout: other.keyboard_layout.or(self.keyboard_layout),
+            privacy: other.privacy.or(self.privacy),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from `path`. A missing file is an empty
+    /// configuration unless `required` is set.
+    pub fn load(path: &Path, required: bool) -> Result<Self, ConfigError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default());
+            }
+            Err(err) => return Err(ConfigError::Read(path.to_owned(), err)),
+        };
+        let config: Self =
+            toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_owned(), e))?;
+        config.check()?;
+        Ok(config)
+    }
+
+    /// Rejects profiles with app IDs that set keys they could not apply.
+    fn check(&self) -> Result<(), ConfigError> {
+        for (name, profile) in &self.profiles {
+            if !profile.app_ids.is_empty()
+                && let Some(key) = profile.startup_keys().next()
+            {
+                return Err(ConfigError::StartupKey(name.clone(), key));
+            }
+        }
+        Ok(())
+    }
+
+    /// The profile named `name`, or else the first one whose name or
+    /// `programs` matches the file name of `program`.
+    pub fn profile(
+        &self,
+        name: Option<&str>,
+        program: &str,
+    ) -> Result<Option<&Settings>, ConfigError> {
+        if let Some(name) = name {
+            return match self.profiles.get(name) {
+                Some(profile) => Ok(Some(profile)),
+                None => Err(ConfigError::UnknownProfile(name.to_string())),
+            };
+        }
+        let program = program_label(program);
+        Ok(self
+            .profiles
+            .iter()
+            .find(|(name, profile)| **name == program || profile.programs.contains(&program))
+            .map(|(_, profile)| profile))
+    }
+
+    /// The defaults with the matching profile applied.
+    pub fn resolve(&self, profile: Option<&str>, program: &str) -> Result<Settings, ConfigError> {
+        let settings = self.defaults.clone();
+        Ok(match self.profile(profile, program)? {
+            Some(profile) => settings.merge(profile.clone()),
+            None => settings,
+        })
+    }
+
+    /// The profiles with app IDs, by name, each resolved with `cli` on top.
+    pub fn app_profiles(&self, cli: &Settings) -> Vec<(&str, Settings)> {
+        self.profiles
+            .iter()
+            .filter(|(_, profile)| !profile.app_ids.is_empty())
+            .map(|(name, profile)| {
+                let settings = self.defaults.clone().merge(profile.clone());
+                let settings = Settings {
+                    app_ids: profile.app_ids.clone(),
+                    ..settings.merge(cli.clone())
+                };
+                (name.as_str(), settings)
+            })
+            .collect()
+    }
+
+    /// The weights configured for a saved session of `program`, for
+    /// totalling it like a new one.
+    pub fn weights_for(
+        &self,
+        profile: Option<&str>,
+        program: &[String],
+    ) -> Result<Weights, ConfigError> {
+        let program = program.first().map_or("", String::as_str);
+        let settings = self.resolve(profile, program)?;
+        Ok(settings.weights.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [defaults]
+        scroll-debounce-ms = 150
+        pause-hotkey = "Super+F11"
+        history = true
+
+        [profiles.firefox]
+        programs = ["firefox-esr", "librewolf"]
+        lap-hotkey = "Super+F12"
+        assert = ["clicks<=10", "total<=45"]
+        filter = ["ButtonMiddle=drop"]
+
+        [profiles.terminal]
+        programs = ["alacritty", "foot"]
+        app-ids = ["Alacritty", "foot"]
+        scroll-debounce-ms = 50
+        count-repeats = true
+        assert = ["keys<=100"]
+        weights = { scrolls = 0.5 }
+
+        [profiles.sway]
+        status-bar = "waybar"
+    "#;
+
+    #[test]
+    fn test_resolve_profiles() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        config.check().unwrap();
+
+        let settings = config.resolve(None, "/usr/bin/librewolf").unwrap();
+        assert_eq!(settings.scroll_debounce_ms, Some(150));
+        assert_eq!(settings.lap_hotkey.unwrap().to_string(), "Super+F12");
+        assert_eq!(settings.pause_hotkey.unwrap().to_string(), "Super+F11");
+        assert_eq!(settings.assert.unwrap().len(), 2);
+        assert_eq!(settings.filter.unwrap()[0].to_string(), "ButtonMiddle=drop");
+
+        let settings = config.resolve(None, "foot").unwrap();
+        assert_eq!(settings.scroll_debounce_ms, Some(50));
+        assert_eq!(settings.count_repeats, Some(true));
+        assert_eq!(settings.history, Some(true));
+        let settings = config.resolve(None, "sway").unwrap();
+        assert_eq!(settings.status_bar, Some(StatusBarFormat::Waybar));
+        let foot = ["foot".to_string()];
+        assert_eq!(config.weights_for(None, &foot).unwrap().scrolls, 0.5);
+        assert_eq!(
+            config.weights_for(None, &["gimp".to_string()]).unwrap(),
+            Weights::default()
+        );
+        assert_eq!(
+            config.weights_for(Some("firefox"), &foot).unwrap(),
+            Weights::default()
+        );
+
+        // No matching profile, only the defaults
+        let settings = config.resolve(None, "gimp").unwrap();
+        assert_eq!(settings.lap_hotkey, None);
+        assert_eq!(settings.scroll_debounce_ms, Some(150));
+
+        // An explicit profile wins over the program name
+        let settings = config.resolve(Some("terminal"), "firefox").unwrap();
+        assert_eq!(settings.scroll_debounce_ms, Some(50));
+        assert!(matches!(
+            config.resolve(Some("nope"), "firefox"),
+            Err(ConfigError::UnknownProfile(_))
+        ));
+    }
+
+    #[test]
+    fn test_app_profiles() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        let cli = Settings {
+            status_bar: Some(StatusBarFormat::I3bar),
+            ..Settings::default()
+        };
+        let profiles = config.app_profiles(&cli);
+        assert_eq!(profiles.len(), 1);
+        let (name, settings) = &profiles[0];
+        assert_eq!(*name, "terminal");
+        assert_eq!(settings.app_ids, ["Alacritty", "foot"]);
+        assert_eq!(settings.pause_hotkey, "Super+F11".parse().ok());
+        assert_eq!(settings.weights.unwrap().scrolls, 0.5);
+        assert_eq!(settings.scroll_debounce_ms, Some(50));
+        assert_eq!(settings.count_repeats, Some(true));
+        assert_eq!(settings.assert.as_ref().unwrap().len(), 1);
+        assert_eq!(settings.status_bar, Some(StatusBarFormat::I3bar));
+    }
+
+    #[test]
+    fn test_reject_startup_keys_with_app_ids() {
+        let config: Config =
+            toml::from_str("[profiles.terminal]\napp-ids = [\"foot\"]\nstatus-bar = \"waybar\"\n")
+                .unwrap();
+        assert!(matches!(
+            config.check(),
+            Err(ConfigError::StartupKey(name, "status-bar")) if name == "terminal"
+        ));
+        // Without app IDs the profile is only applied at startup
+        let config: Config =
+            toml::from_str("[profiles.terminal]\nprograms = [\"foot\"]\nstatus-bar = \"waybar\"\n")
+                .unwrap();
+        assert!(config.check().is_ok());
+    }
+
+    #[test]
+    fn test_merge_settings() {
+        let config = Settings {
+            quiet: Some(true),
+            scroll_debounce_ms: Some(150),
+            ..Settings::default()
+        };
+        let cli = Settings {
+            scroll_debounce_ms: Some(20),
+            ..Settings::default()
+        };
+        let settings = config.merge(cli);
+        assert_eq!(settings.quiet, Some(true));
+        assert_eq!(settings.scroll_debounce_ms, Some(20));
+    }
+
+    #[test]
+    fn test_reject_unknown_keys() {
+        assert!(toml::from_str::<Config>("[defaults]\nquite = true\n").is_err());
+        assert!(toml::from_str::<Config>("[defaults]\nlap-hotkey = \"Super+Nope\"\n").is_err());
+        assert!(toml::from_str::<Config>("[defaults]\nfilter = [\"A=B=C\"]\n").is_err());
+    }
+}
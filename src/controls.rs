@@ -0,0 +1,625 @@
+use {
+    crate::{
+        budget::Budget,
+        clicks::{ClickKind, ClickRecorder, SurfaceKey},
+        filter::{Filter, Input, Rule},
+        hotkey::Hotkey,
+        keyboard::KeyboardLayout,
+        remote::Job,
+    },
+    std::{
+        cell::{Cell, RefCell},
+        collections::HashSet,
+        path::PathBuf,
+        rc::Rc,
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+            mpsc::{Receiver, Sender},
+        },
+        thread::JoinHandle,
+        time::{Duration, Instant},
+    },
+    wl_actions::{
+        Action, Event, Source,
+        laps::{Lap, lap_label},
+        privacy::Privacy,
+        session::{CounterSnapshot, Session},
+        summary::Summary,
+        weights::Weights,
+    },
+};
+
+/// How long without any action before the session counts as idle.
+pub const IDLE_AFTER: Duration = Duration::from_secs(10);
+
+/// How many of the most pressed keys the dashboard shows.
+const TOP_KEYS: usize = 10;
+
+/// What the display thread shows, sent whenever the counters or the state
+/// change.
+#[derive(Clone, Debug)]
+pub struct SessionSnapshot {
+    pub counts: CounterSnapshot,
+    pub weights: Weights,
+    pub paused: bool,
+    pub laps: usize,
+    /// The active time when the snapshot was taken.
+    pub active: Duration,
+    pub taken_at: Instant,
+    /// Filled in by the display thread for the dashboard.
+    pub details: Option<SnapshotDetails>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SnapshotDetails {
+    /// The most pressed keys, labelled according to the privacy level.
+    pub top_keys: Vec<(String, u64)>,
+    /// The actions that went to each window.
+    pub windows: Vec<(String, CounterSnapshot)>,
+    /// The laps so far, including the open one.
+    pub laps: Vec<Lap>,
+}
+
+impl SessionSnapshot {
+    /// The active time at `now`, which keeps running while not paused.
+    pub fn active_time(&self, now: Instant) -> Duration {
+        if self.paused {
+            self.active
+        } else {
+            self.active + now.saturating_duration_since(self.taken_at)
+        }
+    }
+}
+
+/// Notices when the counters last changed, to tell how long the session has
+/// been idle.
+#[derive(Debug)]
+pub struct Activity {
+    count: u64,
+    at: Instant,
+}
+
+impl Activity {
+    pub fn new(now: Instant) -> Self {
+        Self { count: 0, at: now }
+    }
+
+    /// Notes the counters at `now` and returns the time since they last
+    /// changed.
+    pub fn idle_time(&mut self, counts: &CounterSnapshot, now: Instant) -> Duration {
+        let count =
+            counts.key_presses + counts.button_clicks + counts.scroll_steps + counts.touch_taps;
+        if count != self.count {
+            self.count = count;
+            self.at = now;
+        }
+        now.saturating_duration_since(self.at)
+    }
+}
+
+/// What a recognised hotkey does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HotkeyAction {
+    Lap,
+    TogglePause,
+}
+
+/// The hotkeys, which an app profile may still change while the session
+/// runs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Hotkeys {
+    pub lap: Option<Hotkey>,
+    pub pause: Option<Hotkey>,
+    /// Don't forward the hotkeys to the application.
+    pub swallow: bool,
+}
+
+/// A profile selected by the app_id the application sets on its windows,
+/// with the settings that can still change while the session runs.
+#[derive(Clone, Debug)]
+pub struct AppProfile {
+    pub name: String,
+    pub app_ids: Vec<String>,
+    pub hotkeys: Hotkeys,
+    pub filter: Vec<Rule>,
+    pub weights: Weights,
+    pub scroll_debounce: Duration,
+    pub count_repeats: bool,
+    pub privacy: Privacy,
+    pub budget: Budget,
+}
+
+/// A press of a hotkey modifier, held back until it is known whether it is
+/// part of the hotkey.
+#[derive(Clone, Copy, Debug)]
+struct DeferredPress {
+    source: Source,
+    surface: Option<SurfaceKey>,
+    key: u32,
+    at: Instant,
+}
+
+/// Session controls shared by the Wayland handlers on the proxy thread. The
+/// other threads reach them through [`Remote`](crate::remote::Remote) and
+/// the snapshots.
+#[derive(Clone)]
+pub struct Controls {
+    /// The counting engine, fed by the Wayland handlers.
+    pub session: Rc<RefCell<Session>>,
+    /// Cleared when the session ends, to stop the display thread.
+    pub running: Arc<AtomicBool>,
+    /// Where to send a snapshot whenever the counters or the state change.
+    pub snapshots: Option<Sender<SessionSnapshot>>,
+    /// The thread showing the snapshots.
+    pub display: Rc<RefCell<Option<JoinHandle<()>>>>,
+    /// Jobs queued by the other threads.
+    pub jobs: Option<Rc<Receiver<Job>>>,
+    pub socket_path: Option<PathBuf>,
+    /// Where to save the session when it ends.
+    pub history_db: Option<PathBuf>,
+    /// Where to write the summary as JSON when the session ends.
+    pub summary_json: Option<PathBuf>,
+    /// Where the session is recorded, if anywhere.
+    pub recording: Option<PathBuf>,
+    /// Limits checked against the final counters.
+    pub budget: Rc<RefCell<Budget>>,
+    pub clicks: Rc<RefCell<ClickRecorder>>,
+    /// Rules applied to keys and buttons before they reach the client.
+    pub filter: Rc<RefCell<Filter>>,
+    /// Where to write the click heatmap and the raw clicks when the session
+    /// ends.
+    pub click_heatmap: Option<PathBuf>,
+    pub click_csv: Option<PathBuf>,
+    /// Where to write the keyboard heatmap when the session ends.
+    pub keyboard_heatmap: Option<PathBuf>,
+    pub keyboard_layout: KeyboardLayout,
+    pub hotkeys: Rc<Cell<Hotkeys>>,
+    /// The profiles to pick from once the application sets an app_id,
+    /// emptied when one is applied.
+    pub app_profiles: Rc<RefCell<Vec<AppProfile>>>,
+    /// Whether to log the seats, pointers and input seen.
+    pub verbose: bool,
+    /// Presses of hotkey modifiers not counted yet.
+    deferred: Rc<RefCell<Vec<DeferredPress>>>,
+    announce: bool,
+}
+
+impl Controls {
+    pub fn new(session: Session, announce: bool) -> Self {
+        Self {
+            session: Rc::new(RefCell::new(session)),
+            running: Arc::new(AtomicBool::new(true)),
+            snapshots: None,
+            display: Rc::new(RefCell::new(None)),
+            jobs: None,
+            socket_path: None,
+            history_db: None,
+            summary_json: None,
+            recording: None,
+            budget: Rc::new(RefCell::new(Budget::default())),
+            clicks: Rc::new(RefCell::new(ClickRecorder::default())),
+            filter: Rc::new(RefCell::new(Filter::default())),
+            click_heatmap: None,
+            click_csv: None,
+            keyboard_heatmap: None,
+            keyboard_layout: KeyboardLayout::default(),
+            hotkeys: Rc::new(Cell::new(Hotkeys::default())),
+            app_profiles: Rc::new(RefCell::new(Vec::new())),
+            verbose: false,
+            deferred: Rc::new(RefCell::new(Vec::new())),
+            announce,
+        }
+    }
+
+    /// Runs the jobs queued by the other threads.
+    pub fn run_jobs(&self) {
+        if let Some(jobs) = &self.jobs {
+            for job in jobs.try_iter() {
+                job(self);
+            }
+        }
+    }
+
+    pub fn weights(&self) -> Weights {
+        self.session.borrow().weights
+    }
+
+    pub fn summary(&self) -> Summary {
+        let mut summary = self.session.borrow().summary(Instant::now());
+        summary.filtered = self.filter.borrow().filtered().clone();
+        summary
+    }
+
+    /// Sends a snapshot of the counters to the display thread, if there is
+    /// one. Called on every counted event, so it leaves out the details.
+    pub fn publish(&self) {
+        let Some(snapshots) = &self.snapshots else {
+            return;
+        };
+        let now = Instant::now();
+        let session = self.session.borrow();
+        let _ = snapshots.send(SessionSnapshot {
+            counts: session.snapshot(),
+            weights: session.weights,
+            paused: session.is_paused(),
+            laps: session.lap_count(),
+            active: session.active_time(now),
+            taken_at: now,
+            details: None,
+        });
+    }
+
+    /// The details for the dashboard, which asks for them at its own pace.
+    pub fn details(&self) -> SnapshotDetails {
+        let session = self.session.borrow();
+        let mut top_keys: Vec<_> = session.key_detail().into_iter().collect();
+        top_keys.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_keys.truncate(TOP_KEYS);
+        SnapshotDetails {
+            top_keys,
+            windows: self.clicks.borrow().windows(),
+            laps: session.laps(Instant::now()),
+        }
+    }
+
+    /// Stops the display thread and waits for it to restore the terminal.
+    pub fn stop_display(&self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(display) = self.display.borrow_mut().take() {
+            let _ = display.join();
+        }
+    }
+
+    pub fn record_lap(&self, name: Option<String>) -> Lap {
+        let (lap, count) = {
+            let mut session = self.session.borrow_mut();
+            let lap = session.mark_lap(name, Instant::now());
+            (lap, session.lap_count())
+        };
+        if self.announce {
+            eprintln!("\nLap {} recorded{}", count, lap_label(lap.name.as_deref()));
+        }
+        self.publish();
+        lap
+    }
+
+    /// Zeroes the counters and drops the laps, restarting the session clock.
+    pub fn reset(&self) {
+        self.session.borrow_mut().reset(Instant::now());
+        self.clicks.borrow_mut().reset();
+        self.filter.borrow_mut().reset();
+        if self.announce {
+            eprintln!("\nCounters reset");
+        }
+        self.publish();
+    }
+
+    /// Returns what the client sees of a press of `input`, recording the
+    /// press if a filter rule withheld or remapped it.
+    pub fn filter_press(&self, source: Source, input: Input) -> Option<Input> {
+        let now = Instant::now();
+        let (seen, rule) = self.filter.borrow_mut().press(source, input, now);
+        if let Some(rule) = rule {
+            self.session
+                .borrow_mut()
+                .record_filtered(rule.to_string(), now);
+        }
+        seen
+    }
+
+    /// Notes a counted click or tap at surface-local coordinates.
+    pub fn record_click(&self, surface: SurfaceKey, x: f64, y: f64, kind: ClickKind) {
+        let now = self.session.borrow().active_time(Instant::now());
+        self.clicks.borrow_mut().record(surface, x, y, kind, now);
+    }
+
+    /// Adds a counted action to the window it went to.
+    pub fn count_window(&self, surface: SurfaceKey, action: Action) {
+        let mut counts = CounterSnapshot::default();
+        match action {
+            Action::Key(_) => counts.key_presses = 1,
+            Action::Repeat(_, repeats) if self.session.borrow().count_repeats => {
+                counts.key_presses = repeats;
+            }
+            Action::Repeat(..) => return,
+            Action::Click(_) => counts.button_clicks = 1,
+            Action::Scroll => counts.scroll_steps = 1,
+            Action::Touch => counts.touch_taps = 1,
+        }
+        self.clicks.borrow_mut().count(surface, counts);
+    }
+
+    /// Returns false if counting was already paused.
+    pub fn pause(&self) -> bool {
+        if !self.session.borrow_mut().pause(Instant::now()) {
+            return false;
+        }
+        self.publish();
+        if self.announce {
+            eprintln!("\nCounting paused");
+        }
+        true
+    }
+
+    /// Returns false if counting was not paused.
+    pub fn resume(&self) -> bool {
+        if !self.session.borrow_mut().resume(Instant::now()) {
+            return false;
+        }
+        self.publish();
+        if self.announce {
+            eprintln!("\nCounting resumed");
+        }
+        true
+    }
+
+    pub fn toggle_pause(&self) {
+        if !self.pause() {
+            self.resume();
+        }
+    }
+
+    /// Applies the first app profile that lists `app_id`, unless one was
+    /// applied before.
+    pub fn set_app_id(&self, app_id: &str) {
+        let profile = {
+            let mut profiles = self.app_profiles.borrow_mut();
+            let Some(profile) = profiles
+                .iter()
+                .find(|profile| profile.app_ids.iter().any(|id| id == app_id))
+                .cloned()
+            else {
+                return;
+            };
+            profiles.clear();
+            profile
+        };
+        self.hotkeys.set(profile.hotkeys);
+        self.filter.borrow_mut().set_rules(profile.filter);
+        {
+            let mut session = self.session.borrow_mut();
+            session.weights = profile.weights;
+            session.set_counting(profile.scroll_debounce, profile.count_repeats);
+            session.set_privacy(profile.privacy);
+        }
+        *self.budget.borrow_mut() = profile.budget;
+        if self.announce {
+            eprintln!("\nUsing profile {} for {}", profile.name, app_id);
+        }
+        self.publish();
+    }
+
+    fn hotkey_action(&self, key: u32, pressed: &HashSet<u32>) -> Option<HotkeyAction> {
+        let hotkeys = self.hotkeys.get();
+        let matches = |hotkey: Option<Hotkey>| hotkey.is_some_and(|h| h.matches(key, pressed));
+        if matches(hotkeys.lap) {
+            Some(HotkeyAction::Lap)
+        } else if matches(hotkeys.pause) {
+            Some(HotkeyAction::TogglePause)
+        } else {
+            None
+        }
+    }
+
+    fn is_hotkey_modifier(&self, key: u32) -> bool {
+        let hotkeys = self.hotkeys.get();
+        [hotkeys.lap, hotkeys.pause]
+            .into_iter()
+            .flatten()
+            .any(|hotkey| hotkey.is_modifier_key(key))
+    }
+
+    /// Feeds an event to the session at `now`, counting it for `surface` as
+    /// well. The held back modifier presses are counted first.
+    pub fn handle_event(
+        &self,
+        source: Source,
+        surface: Option<SurfaceKey>,
+        event: Event,
+        now: Instant,
+    ) -> Option<Action> {
+        // A held modifier repeating is no sign that it is used on its own
+        if let Event::KeyRepeated { key } = event
+            && self
+                .deferred
+                .borrow()
+                .iter()
+                .any(|press| press.source == source && press.key == key)
+        {
+            return None;
+        }
+        self.count_deferred();
+        let action = self.session.borrow_mut().handle_from(source, event, now);
+        if let Some(action) = action {
+            if let Some(surface) = surface {
+                self.count_window(surface, action);
+            }
+            self.publish();
+        }
+        action
+    }
+
+    /// Handles a key press at `now` and returns whether it was a hotkey.
+    /// Presses of the hotkey modifiers are held back until the next event,
+    /// so that a chord as a whole is not counted as an action.
+    pub fn key_pressed(
+        &self,
+        source: Source,
+        surface: Option<SurfaceKey>,
+        key: u32,
+        now: Instant,
+    ) -> bool {
+        let mut pressed = self.session.borrow().pressed_keys(source.seat);
+        pressed.extend(
+            self.deferred
+                .borrow()
+                .iter()
+                .filter(|press| press.source.seat == source.seat)
+                .map(|press| press.key),
+        );
+        if let Some(action) = self.hotkey_action(key, &pressed) {
+            // The modifiers are part of the hotkey, they are held but not
+            // counted
+            let was_new = {
+                let mut session = self.session.borrow_mut();
+                self.deferred.borrow_mut().retain(|press| {
+                    let part = press.source.seat == source.seat;
+                    if part {
+                        session.hold_key(press.source, press.key);
+                    }
+                    !part
+                });
+                session.hold_key(source, key)
+            };
+            if was_new {
+                self.run_hotkey(action);
+            }
+            return true;
+        }
+        if self.is_hotkey_modifier(key) {
+            self.deferred.borrow_mut().push(DeferredPress {
+                source,
+                surface,
+                key,
+                at: now,
+            });
+        } else {
+            self.handle_event(source, surface, Event::KeyPressed { key }, now);
+        }
+        false
+    }
+
+    /// Counts the held back modifier presses, which turned out not to be
+    /// part of a hotkey.
+    pub fn count_deferred(&self) {
+        let deferred = std::mem::take(&mut *self.deferred.borrow_mut());
+        for press in deferred {
+            let event = Event::KeyPressed { key: press.key };
+            let action = self
+                .session
+                .borrow_mut()
+                .handle_from(press.source, event, press.at);
+            if let Some(action) = action {
+                if let Some(surface) = press.surface {
+                    self.count_window(surface, action);
+                }
+                self.publish();
+            }
+        }
+    }
+
+    fn run_hotkey(&self, action: HotkeyAction) {
+        match action {
+            HotkeyAction::Lap => {
+                self.record_lap(None);
+            }
+            HotkeyAction::TogglePause => self.toggle_pause(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUPER: u32 = 125;
+    const F12: u32 = 88;
+    const A: u32 = 30;
+
+    #[test]
+    fn test_hotkey_not_counted() {
+        let controls = Controls::new(Session::new(Instant::now(), Vec::new()), false);
+        controls.hotkeys.set(Hotkeys {
+            lap: Some("Super+F12".parse().unwrap()),
+            ..Hotkeys::default()
+        });
+        let source = Source::default();
+        let window = Some(1);
+        let press = |key| controls.key_pressed(source, window, key, Instant::now());
+        let release =
+            |key| controls.handle_event(source, window, Event::KeyReleased { key }, Instant::now());
+
+        // Super+F12 records a lap and is no action
+        assert!(!press(A));
+        release(A);
+        assert!(!press(SUPER));
+        assert!(press(F12));
+        release(F12);
+        // Still held, so F12 again is another lap
+        assert!(press(F12));
+        release(F12);
+        release(SUPER);
+        // Super on its own and Super+A are actions
+        assert!(!press(SUPER));
+        release(SUPER);
+        assert!(!press(SUPER));
+        assert!(!press(A));
+
+        let summary = controls.summary();
+        assert_eq!(summary.counters.key_presses, 4);
+        assert_eq!(summary.klm.keystrokes, 4);
+        let laps: Vec<_> = summary
+            .laps
+            .iter()
+            .map(|lap| lap.counters.key_presses)
+            .collect();
+        assert_eq!(laps, [1, 0, 3]);
+        let windows = controls.clicks.borrow().windows();
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows[0].1.key_presses, 4);
+        assert_eq!(summary.modalities.sequence.len(), 1);
+        assert_eq!(summary.modalities.sequence[0].actions, 4);
+    }
+
+    #[test]
+    fn test_app_profile() {
+        let controls = Controls::new(Session::new(Instant::now(), Vec::new()), false);
+        let profile = |name: &str, app_id: &str, keys| AppProfile {
+            name: name.to_string(),
+            app_ids: vec![app_id.to_string()],
+            hotkeys: Hotkeys {
+                lap: Some("Super+F12".parse().unwrap()),
+                ..Hotkeys::default()
+            },
+            filter: vec!["A=drop".parse().unwrap()],
+            weights: Weights {
+                keys,
+                ..Weights::default()
+            },
+            scroll_debounce: Duration::ZERO,
+            count_repeats: true,
+            privacy: Privacy::Hash,
+            budget: Budget {
+                limits: vec!["keys<=1".parse().unwrap()],
+            },
+        };
+        *controls.app_profiles.borrow_mut() = vec![
+            profile("browser", "org.mozilla.firefox", 2.0),
+            profile("terminal", "foot", 3.0),
+        ];
+        let source = Source::default();
+
+        controls.set_app_id("org.gnome.Nautilus");
+        assert_eq!(controls.weights(), Weights::default());
+        controls.set_app_id("org.mozilla.firefox");
+        assert_eq!(controls.weights().keys, 2.0);
+        {
+            let session = controls.session.borrow();
+            assert_eq!(session.scroll_debounce, Duration::ZERO);
+            assert!(session.count_repeats);
+            assert_eq!(session.privacy(), Privacy::Hash);
+        }
+        assert_eq!(controls.budget.borrow().limits.len(), 1);
+        assert!(!controls.key_pressed(source, None, SUPER, Instant::now()));
+        assert!(controls.key_pressed(source, None, F12, Instant::now()));
+        let filtered = controls
+            .filter
+            .borrow_mut()
+            .press(source, Input::Key(A), Instant::now());
+        assert_eq!(filtered.0, None);
+        // Only the first match applies
+        controls.set_app_id("foot");
+        assert_eq!(controls.weights().keys, 2.0);
+    }
+}
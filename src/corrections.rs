@@ -0,0 +1,114 @@
+//! Counts the key presses that undo earlier work: erasing and undo.
+
+use {
+    crate::{
+        detector::Detector,
+        event::{Action, Event},
+    },
+    std::{collections::HashSet, time::Duration},
+};
+
+const BACKSPACE: u32 = 14;
+const DELETE: u32 = 111;
+const Z: u32 = 44;
+const CONTROL_L: u32 = 29;
+const CONTROL_R: u32 = 97;
+
+#[derive(Debug, Default)]
+pub struct Corrections {
+    erases: u64,
+    undos: u64,
+    keys: u64,
+    controls_held: HashSet<u32>,
+}
+
+impl Detector for Corrections {
+    fn name(&self) -> &'static str {
+        "corrections"
+    }
+
+    fn observe(&mut self, event: &Event, action: Option<Action>, _at: Duration) {
+        match (*event, action) {
+            (Event::KeyPressed { key }, Some(Action::Key(_))) => {
+                self.keys += 1;
+                match key {
+                    BACKSPACE | DELETE => self.erases += 1,
+                    Z if !self.controls_held.is_empty() => self.undos += 1,
+                    _ => {}
+                }
+                if matches!(key, CONTROL_L | CONTROL_R) {
+                    self.controls_held.insert(key);
+                }
+            }
+            (Event::KeyReleased { key }, _) => {
+                self.controls_held.remove(&key);
+            }
+            _ => {}
+        }
+        // A held Backspace erases once per repeat
+        if let Some(Action::Repeat(key, repeats)) = action {
+            self.keys += repeats;
+            match key {
+                BACKSPACE | DELETE => self.erases += repeats,
+                Z if !self.controls_held.is_empty() => self.undos += repeats,
+                _ => {}
+            }
+        }
+    }
+
+    fn metrics(&self, _at: Duration) -> Vec<(&'static str, f64)> {
+        let share = if self.keys > 0 {
+            (self.erases + self.undos) as f64 / self.keys as f64
+        } else {
+            0.0
+        };
+        vec![
+            ("erases", self.erases as f64),
+            ("undos", self.undos as f64),
+            ("share", share),
+        ]
+    }
+
+    fn reset(&mut self) {
+        // Held modifiers stay held
+        let controls_held = std::mem::take(&mut self.controls_held);
+        *self = Self {
+            controls_held,
+            ..Self::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corrections() {
+        let mut corrections = Corrections::default();
+        let mut press = |key| {
+            let event = Event::KeyPressed { key };
+            corrections.observe(&event, Some(Action::Key(key)), Duration::ZERO);
+        };
+        // "ab", Backspace, Ctrl+Z
+        press(30);
+        press(48);
+        press(BACKSPACE);
+        press(CONTROL_L);
+        press(Z);
+        corrections.observe(&Event::KeyReleased { key: CONTROL_L }, None, Duration::ZERO);
+        // A plain z is not an undo
+        let event = Event::KeyPressed { key: Z };
+        corrections.observe(&event, Some(Action::Key(Z)), Duration::ZERO);
+        // Backspace held down for 4 repeats
+        let event = Event::KeyPressed { key: BACKSPACE };
+        corrections.observe(&event, Some(Action::Key(BACKSPACE)), Duration::ZERO);
+        let event = Event::KeyReleased { key: BACKSPACE };
+        corrections.observe(&event, Some(Action::Repeat(BACKSPACE, 4)), Duration::ZERO);
+
+        assert_eq!(
+            corrections.metrics(Duration::ZERO),
+            [("erases", 6.0), ("undos", 1.0), ("share", 7.0 / 11.0)]
+        );
+    }
+}
@@ -0,0 +1,338 @@
+//! A full-screen live view of the session on the terminal.
+//!
+//! The dashboard takes over stderr with the alternate screen and redraws the
+//! whole frame on every tick of the display thread, so output of the wrapped
+//! program that lands in between is painted over.
+
+use {
+    crate::controls::{Activity, IDLE_AFTER, SessionSnapshot},
+    std::{
+        collections::VecDeque,
+        io::{self, Write},
+        time::{Duration, Instant},
+    },
+    wl_actions::{
+        laps::lap_label, session::CounterSnapshot, summary::format_duration, weights::format_total,
+    },
+};
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The sliding window the actions per minute are counted over.
+const APM_WINDOW: Duration = Duration::from_secs(60);
+/// How often the total is sampled for the sparkline, one column each.
+const SAMPLE_EVERY: Duration = Duration::from_secs(1);
+/// Enough samples for the window plus a wide terminal.
+const MAX_SAMPLES: usize = 60 + 400;
+
+/// How many windows and laps are listed.
+const LIST_LEN: usize = 5;
+
+/// The weighted total sampled once per second.
+#[derive(Debug, Default)]
+pub struct ApmHistory {
+    samples: VecDeque<(Instant, f64)>,
+}
+
+impl ApmHistory {
+    /// Notes the total at `now`, unless the last sample is too recent.
+    pub fn record(&mut self, now: Instant, total: f64) {
+        if self
+            .samples
+            .back()
+            .is_some_and(|&(at, _)| now.saturating_duration_since(at) < SAMPLE_EVERY)
+        {
+            return;
+        }
+        self.samples.push_back((now, total));
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    /// The actions in the window up to sample `i`.
+    fn apm_at(&self, i: usize) -> f64 {
+        let (at, total) = self.samples[i];
+        let start = self
+            .samples
+            .partition_point(|&(sampled, _)| at.saturating_duration_since(sampled) > APM_WINDOW);
+        total - self.samples[start].1
+    }
+
+    /// The actions in the last minute.
+    pub fn apm(&self) -> f64 {
+        match self.samples.len() {
+            0 => 0.0,
+            len => self.apm_at(len - 1),
+        }
+    }
+
+    /// The actions per minute at each of the last `n` samples, oldest first.
+    pub fn series(&self, n: usize) -> Vec<f64> {
+        let len = self.samples.len();
+        (len.saturating_sub(n)..len)
+            .map(|i| self.apm_at(i))
+            .collect()
+    }
+}
+
+/// Draws `values` scaled to the largest, with a blank for nothing.
+pub fn sparkline(values: &[f64]) -> String {
+    let max = values.iter().copied().fold(1.0, f64::max);
+    values
+        .iter()
+        .map(|&value| {
+            if value <= 0.0 {
+                ' '
+            } else {
+                SPARKS[((value / max) * 7.0).round() as usize]
+            }
+        })
+        .collect()
+}
+
+fn counts_line(counts: &CounterSnapshot) -> String {
+    format!(
+        "Keys: {} | Clicks: {} | Scrolls: {} | Touch: {}",
+        counts.key_presses, counts.button_clicks, counts.scroll_steps, counts.touch_taps
+    )
+}
+
+/// Renders the frame for a terminal of `width` columns and `height` rows.
+pub fn render(
+    program: &str,
+    snapshot: &SessionSnapshot,
+    history: &ApmHistory,
+    idle: Duration,
+    now: Instant,
+    (width, height): (usize, usize),
+) -> Vec<String> {
+    let state = if snapshot.paused {
+        "PAUSED".to_string()
+    } else if idle >= IDLE_AFTER {
+        format!("IDLE for {}", format_duration(idle))
+    } else {
+        "ACTIVE".to_string()
+    };
+    let active = snapshot.active_time(now);
+    let minutes = active.as_secs_f64() / 60.0;
+    let total = snapshot.weights.total(&snapshot.counts);
+    let average = if minutes > 0.0 { total / minutes } else { 0.0 };
+
+    let mut lines = vec![
+        format!("wl-actions: {} | {}", program, state),
+        format!(
+            "Duration: {} | Laps: {}",
+            format_duration(active),
+            snapshot.laps
+        ),
+        String::new(),
+        counts_line(&snapshot.counts),
+        format!(
+            "Total actions: {} ({})",
+            format_total(total),
+            snapshot.weights
+        ),
+        String::new(),
+        format!(
+            "Actions per minute: {} in the last minute | {:.1} on average",
+            format_total(history.apm()),
+            average
+        ),
+        sparkline(&history.series(width)),
+    ];
+
+    if let Some(details) = &snapshot.details {
+        if !details.top_keys.is_empty() {
+            lines.push(String::new());
+            lines.push("Top keys".to_string());
+            let keys: Vec<_> = details
+                .top_keys
+                .iter()
+                .map(|(key, presses)| format!("{} {}", key, presses))
+                .collect();
+            lines.push(format!("  {}", keys.join(" | ")));
+        }
+        if !details.windows.is_empty() {
+            lines.push(String::new());
+            lines.push("Windows".to_string());
+            let mut windows = details.windows.clone();
+            windows.sort_by(|a, b| {
+                let weights = snapshot.weights;
+                weights.total(&b.1).total_cmp(&weights.total(&a.1))
+            });
+            for (label, counts) in windows.iter().take(LIST_LEN) {
+                lines.push(format!("  {}: {}", label, counts_line(counts)));
+            }
+        }
+        if !details.laps.is_empty() {
+            lines.push(String::new());
+            lines.push("Laps".to_string());
+            let skipped = details.laps.len().saturating_sub(LIST_LEN);
+            for (i, lap) in details.laps.iter().enumerate().skip(skipped) {
+                lines.push(format!(
+                    "  Lap {}{}: {} | {}",
+                    i + 1,
+                    lap_label(lap.name.as_deref()),
+                    format_duration(lap.duration),
+                    counts_line(&lap.counts)
+                ));
+            }
+        }
+    }
+
+    lines.truncate(height);
+    for line in &mut lines {
+        if let Some((end, _)) = line.char_indices().nth(width) {
+            line.truncate(end);
+        }
+    }
+    lines
+}
+
+/// The dashboard on stderr, restoring the terminal when dropped.
+pub struct Dashboard {
+    program: String,
+    history: ApmHistory,
+    activity: Activity,
+}
+
+impl Dashboard {
+    /// Switches stderr to the alternate screen.
+    pub fn new(program: String) -> Self {
+        let mut stderr = io::stderr();
+        let _ = write!(stderr, "\x1b[?1049h\x1b[?25l");
+        let _ = stderr.flush();
+        Self {
+            program,
+            history: ApmHistory::default(),
+            activity: Activity::new(Instant::now()),
+        }
+    }
+
+    /// Called by the display thread on every tick.
+    pub fn update(&mut self, snapshot: &SessionSnapshot) {
+        let now = Instant::now();
+        self.history
+            .record(now, snapshot.weights.total(&snapshot.counts));
+        let idle = self.activity.idle_time(&snapshot.counts, now);
+        let lines = render(
+            &self.program,
+            snapshot,
+            &self.history,
+            idle,
+            now,
+            terminal_size(),
+        );
+        // No newline after the last row, which would scroll a full frame
+        let mut frame = String::from("\x1b[H");
+        frame.push_str(&lines.join("\x1b[K\r\n"));
+        frame.push_str("\x1b[K\x1b[J");
+        let mut stderr = io::stderr().lock();
+        let _ = stderr.write_all(frame.as_bytes());
+        let _ = stderr.flush();
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        let mut stderr = io::stderr();
+        let _ = write!(stderr, "\x1b[?25h\x1b[?1049l");
+        let _ = stderr.flush();
+    }
+}
+
+/// The columns and rows of the terminal on stderr.
+fn terminal_size() -> (usize, usize) {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: TIOCGWINSZ only writes the winsize it is given
+    let result = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if result == 0 && size.ws_col > 0 && size.ws_row > 0 {
+        (size.ws_col as usize, size.ws_row as usize)
+    } else {
+        (80, 24)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::controls::SnapshotDetails,
+        wl_actions::{laps::Lap, weights::Weights},
+    };
+
+    #[test]
+    fn test_apm_history() {
+        let start = Instant::now();
+        let secs = |n| start + Duration::from_secs(n);
+        let mut history = ApmHistory::default();
+        for (at, total) in [(0, 0.0), (30, 30.0), (60, 90.0), (90, 90.0), (120, 100.0)] {
+            history.record(secs(at), total);
+        }
+        // Too soon after the last sample
+        history.record(start + Duration::from_millis(120_500), 500.0);
+
+        assert_eq!(history.series(10), [0.0, 30.0, 90.0, 60.0, 10.0]);
+        assert_eq!(history.series(2), [60.0, 10.0]);
+        assert_eq!(history.apm(), 10.0);
+        assert_eq!(sparkline(&[0.0, 30.0, 90.0, 60.0, 10.0]), " ▃█▆▂");
+    }
+
+    #[test]
+    fn test_render() {
+        let now = Instant::now();
+        let counts = CounterSnapshot {
+            key_presses: 40,
+            button_clicks: 12,
+            scroll_steps: 3,
+            touch_taps: 0,
+        };
+        let snapshot = SessionSnapshot {
+            counts,
+            weights: Weights::default(),
+            paused: false,
+            laps: 1,
+            active: Duration::from_secs(120),
+            taken_at: now,
+            details: Some(SnapshotDetails {
+                top_keys: vec![("BackSpace".to_string(), 12), ("A".to_string(), 9)],
+                windows: vec![("Inbox".to_string(), counts)],
+                laps: vec![Lap {
+                    name: Some("login".to_string()),
+                    duration: Duration::from_secs(65),
+                    counts,
+                }],
+            }),
+        };
+        let history = ApmHistory::default();
+        let lines = render(
+            "firefox",
+            &snapshot,
+            &history,
+            Duration::from_secs(12),
+            now,
+            (80, 40),
+        );
+        assert_eq!(lines[0], "wl-actions: firefox | IDLE for 12s");
+        assert_eq!(lines[3], "Keys: 40 | Clicks: 12 | Scrolls: 3 | Touch: 0");
+        assert!(lines.contains(&"  BackSpace 12 | A 9".to_string()));
+        assert!(
+            lines.contains(&"  Inbox: Keys: 40 | Clicks: 12 | Scrolls: 3 | Touch: 0".to_string())
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.starts_with("  Lap 1 (login): 1m 5s"))
+        );
+
+        // Cut to the terminal
+        let lines = render("firefox", &snapshot, &history, Duration::ZERO, now, (12, 2));
+        assert_eq!(lines, ["wl-actions: ", "Duration: 2m"]);
+    }
+}
@@ -0,0 +1,34 @@
+//! Pluggable detectors that derive their own metrics from the event stream.
+//!
+//! A detector sees every event the session handles while it is not paused,
+//! along with what the event was counted as, and the key releases while it
+//! is, so that it never misses a key being let go. Its metrics end up in the
+//! summary under `<detector>.<metric>`, so a new metric is one module that
+//! implements [`Detector`] and one [`Session::add_detector`] call.
+//!
+//! [`Session::add_detector`]: crate::session::Session::add_detector
+
+use {
+    crate::event::{Action, Event},
+    std::{fmt, time::Duration},
+};
+
+pub trait Detector: fmt::Debug + Send {
+    /// Prefixes the names of the metrics; lowercase, without dots.
+    fn name(&self) -> &'static str;
+
+    /// Called for every event while counting. `at` is the active session
+    /// time of the event.
+    fn observe(&mut self, event: &Event, action: Option<Action>, at: Duration);
+
+    /// The metrics at active session time `at`.
+    fn metrics(&self, at: Duration) -> Vec<(&'static str, f64)>;
+
+    /// Starts over, e.g. when the counters are reset.
+    fn reset(&mut self);
+}
+
+/// The detectors that `wl-actions` registers for every session.
+pub fn builtin() -> Vec<Box<dyn Detector>> {
+    vec![Box::new(crate::corrections::Corrections::default())]
+}
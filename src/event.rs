@@ -0,0 +1,67 @@
+//! Input events as the counting engine sees them, independent of the
+//! transport they were read from.
+
+use serde::{Deserialize, Serialize};
+
+/// A normalized input event. Pointer and touch coordinates are
+/// surface-local.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    /// A key went down, with its evdev code. Repeats of a key that is already
+    /// down are not counted.
+    KeyPressed {
+        key: u32,
+    },
+    /// A key released. Releasing a held key may be counted as the repeats
+    /// the client generated for it meanwhile.
+    KeyReleased {
+        key: u32,
+    },
+    /// A repeat of a held key sent by the compositor, from `wl_keyboard`
+    /// version 10.
+    KeyRepeated {
+        key: u32,
+    },
+    /// A pointer button went down, with its evdev code.
+    ButtonPressed {
+        button: u32,
+    },
+    ButtonReleased {
+        button: u32,
+    },
+    /// The pointer entered a surface.
+    PointerEnter {
+        x: f64,
+        y: f64,
+    },
+    PointerMotion {
+        x: f64,
+        y: f64,
+    },
+    /// Any scroll event; bursts closer together than the scroll debounce
+    /// count as one step.
+    Scroll,
+    TouchDown,
+}
+
+/// Where an event came from: a seat and one of its device objects, both
+/// numbered by the caller. Press state is tracked per seat and device, so
+/// the same key held on two seats counts twice while duplicate events from
+/// two keyboard objects of one seat count once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Source {
+    pub seat: u32,
+    pub device: u64,
+}
+
+/// What an event was counted as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Key(u32),
+    /// Repeats of a held key: the key and how many.
+    Repeat(u32, u64),
+    Click(u32),
+    Scroll,
+    Touch,
+}
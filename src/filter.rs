@@ -0,0 +1,382 @@
+//! Rules that drop, remap or rate-limit keys and buttons on their way to the
+//! client, e.g. to disable Caps Lock or the middle click paste.
+//!
+//! Rules are decided once per press and seat, and the release and repeats of
+//! a press follow that decision, so every keyboard of the seat sees the same
+//! thing and the client never gets a release without its press.
+
+use {
+    serde::Deserialize,
+    std::{
+        collections::{BTreeMap, HashMap, HashSet, VecDeque},
+        fmt,
+        str::FromStr,
+        time::{Duration, Instant},
+    },
+    thiserror::Error,
+    wl_actions::{
+        Source,
+        keys::{key_code, key_name},
+    },
+};
+
+/// Pointer button names and their evdev codes.
+const BUTTONS: &[(&str, u32)] = &[
+    ("ButtonLeft", 0x110),
+    ("ButtonRight", 0x111),
+    ("ButtonMiddle", 0x112),
+    ("ButtonSide", 0x113),
+    ("ButtonExtra", 0x114),
+    ("ButtonForward", 0x115),
+    ("ButtonBack", 0x116),
+];
+
+#[derive(Debug, Error)]
+pub enum FilterError {
+    #[error("`{0}` is not of the form `<key>=<action>`, e.g. `Caps_Lock=drop`")]
+    Syntax(String),
+    #[error("unknown key or button `{0}`")]
+    UnknownInput(String),
+    #[error("`{0}` remaps a key to a button or a button to a key")]
+    MixedRemap(String),
+    #[error("`{0}` is not a rate limit such as `5/s` or `2/10s`")]
+    InvalidLimit(String),
+}
+
+/// A key or a pointer button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Input {
+    Key(u32),
+    Button(u32),
+}
+
+impl FromStr for Input {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(&(_, code)) = BUTTONS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+        {
+            return Ok(Self::Button(code));
+        }
+        key_code(s)
+            .map(Self::Key)
+            .ok_or_else(|| FilterError::UnknownInput(s.to_string()))
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Key(code) => match key_name(code) {
+                Some(name) => f.write_str(name),
+                None => write!(f, "key{}", code),
+            },
+            Self::Button(code) => match BUTTONS.iter().find(|&&(_, button)| button == code) {
+                Some((name, _)) => f.write_str(name),
+                None => write!(f, "button{}", code),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleAction {
+    /// The client never sees the input.
+    Drop,
+    /// The client sees another key or button instead.
+    Remap(Input),
+    /// Presses beyond `count` within `per` are dropped.
+    Limit { count: u32, per: Duration },
+}
+
+/// A single rule such as `Caps_Lock=drop`, `Caps_Lock=Escape` or
+/// `BackSpace=5/s`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rule {
+    pub input: Input,
+    pub action: RuleAction,
+}
+
+impl FromStr for Rule {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (input, action) = s
+            .split_once('=')
+            .map(|(input, action)| (input.trim(), action.trim()))
+            .filter(|(input, action)| !input.is_empty() && !action.is_empty())
+            .ok_or_else(|| FilterError::Syntax(s.to_string()))?;
+        let input: Input = input.parse()?;
+        let action = if action.eq_ignore_ascii_case("drop") {
+            RuleAction::Drop
+        } else if let Some((count, per)) = action.split_once('/') {
+            let invalid = || FilterError::InvalidLimit(action.to_string());
+            let count = count.trim().parse().map_err(|_| invalid())?;
+            let per = parse_period(per.trim()).ok_or_else(invalid)?;
+            RuleAction::Limit { count, per }
+        } else {
+            let to: Input = action.parse()?;
+            if matches!(input, Input::Key(_)) != matches!(to, Input::Key(_)) {
+                return Err(FilterError::MixedRemap(s.to_string()));
+            }
+            RuleAction::Remap(to)
+        };
+        Ok(Self { input, action })
+    }
+}
+
+/// Parses `s`, `min` or `h`, optionally after a number, e.g. `10s`.
+fn parse_period(s: &str) -> Option<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, unit) = s.split_at(split);
+    let n: u64 = if n.is_empty() { 1 } else { n.parse().ok()? };
+    let secs = match unit {
+        "s" => 1,
+        "min" => 60,
+        "h" => 3600,
+        _ => return None,
+    };
+    Some(Duration::from_secs(n * secs)).filter(|per| !per.is_zero())
+}
+
+impl TryFrom<String> for Rule {
+    type Error = FilterError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=", self.input)?;
+        match self.action {
+            RuleAction::Drop => f.write_str("drop"),
+            RuleAction::Remap(to) => write!(f, "{}", to),
+            RuleAction::Limit { count, per } if per.as_secs() == 1 => write!(f, "{}/s", count),
+            RuleAction::Limit { count, per } => write!(f, "{}/{}s", count, per.as_secs()),
+        }
+    }
+}
+
+/// The decision for a held key or button.
+#[derive(Debug)]
+struct Held {
+    /// The devices of the seat that saw the press.
+    devices: HashSet<u64>,
+    /// What the client sees, `None` if it sees nothing.
+    to: Option<Input>,
+}
+
+/// Applies the rules to the input of every seat and counts what they
+/// filtered.
+#[derive(Debug, Default)]
+pub struct Filter {
+    rules: Vec<Rule>,
+    held: HashMap<(u32, Input), Held>,
+    /// The recent passed presses of rate-limited inputs, by seat.
+    recent: HashMap<(u32, Input), VecDeque<Instant>>,
+    /// The presses each rule dropped or remapped, by rule.
+    filtered: BTreeMap<String, u64>,
+}
+
+impl Filter {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            rules,
+            ..Self::default()
+        }
+    }
+
+    /// Replaces the rules. Presses that are down keep what was done to them.
+    pub fn set_rules(&mut self, rules: Vec<Rule>) {
+        self.rules = rules;
+        self.recent.clear();
+    }
+
+    /// Returns what the client sees of a press of `input` on `source`, and
+    /// the rule that withheld or remapped it, if one did.
+    pub fn press(
+        &mut self,
+        source: Source,
+        input: Input,
+        now: Instant,
+    ) -> (Option<Input>, Option<Rule>) {
+        if let Some(held) = self.held.get_mut(&(source.seat, input)) {
+            // Another keyboard of the seat, or a press without a release
+            held.devices.insert(source.device);
+            return (held.to, None);
+        }
+        let Some(&rule) = self.rules.iter().find(|rule| rule.input == input) else {
+            return (Some(input), None);
+        };
+        let to = match rule.action {
+            RuleAction::Drop => None,
+            RuleAction::Remap(to) => Some(to),
+            RuleAction::Limit { count, per } => {
+                let recent = self.recent.entry((source.seat, input)).or_default();
+                while recent
+                    .front()
+                    .is_some_and(|&at| now.saturating_duration_since(at) >= per)
+                {
+                    recent.pop_front();
+                }
+                if recent.len() < count as usize {
+                    recent.push_back(now);
+                    Some(input)
+                } else {
+                    None
+                }
+            }
+        };
+        let filtered = (to != Some(input)).then_some(rule);
+        if filtered.is_some() {
+            *self.filtered.entry(rule.to_string()).or_default() += 1;
+        }
+        self.held.insert(
+            (source.seat, input),
+            Held {
+                devices: HashSet::from([source.device]),
+                to,
+            },
+        );
+        (to, filtered)
+    }
+
+    /// Returns what the client sees of a repeat of the held `input`.
+    pub fn repeat(&self, source: Source, input: Input) -> Option<Input> {
+        match self.held.get(&(source.seat, input)) {
+            Some(held) => held.to,
+            None => Some(input),
+        }
+    }
+
+    /// Returns what the client sees of a release of `input` on `source`.
+    pub fn release(&mut self, source: Source, input: Input) -> Option<Input> {
+        let key = (source.seat, input);
+        let Some(held) = self.held.get_mut(&key) else {
+            return Some(input);
+        };
+        let to = held.to;
+        held.devices.remove(&source.device);
+        if held.devices.is_empty() {
+            self.held.remove(&key);
+        }
+        to
+    }
+
+    /// Forgets the presses held on a device that left or went away.
+    pub fn release_device(&mut self, source: Source) {
+        self.held.retain(|&(seat, _), held| {
+            if seat == source.seat {
+                held.devices.remove(&source.device);
+            }
+            !held.devices.is_empty()
+        });
+    }
+
+    pub fn filtered(&self) -> &BTreeMap<String, u64> {
+        &self.filtered
+    }
+
+    pub fn reset(&mut self) {
+        self.filtered.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPS_LOCK: Input = Input::Key(58);
+    const ESCAPE: Input = Input::Key(1);
+    const BACKSPACE: Input = Input::Key(14);
+    const MIDDLE: Input = Input::Button(0x112);
+
+    #[test]
+    fn test_parse_rules() {
+        let rule: Rule = " caps_lock = drop ".parse().unwrap();
+        assert_eq!(rule.action, RuleAction::Drop);
+        assert_eq!(rule.to_string(), "Caps_Lock=drop");
+        let rule: Rule = "Caps_Lock=Esc".parse().unwrap();
+        assert_eq!(rule.action, RuleAction::Remap(ESCAPE));
+        let rule: Rule = "ButtonMiddle=drop".parse().unwrap();
+        assert_eq!(rule.input, MIDDLE);
+        let rule: Rule = "BackSpace=2/10s".parse().unwrap();
+        assert_eq!(rule.to_string(), "BackSpace=2/10s");
+        assert_eq!("A=30/min".parse::<Rule>().unwrap().to_string(), "A=30/60s");
+
+        assert!(matches!(
+            "Caps_Lock".parse::<Rule>(),
+            Err(FilterError::Syntax(_))
+        ));
+        assert!(matches!(
+            "Hyper=drop".parse::<Rule>(),
+            Err(FilterError::UnknownInput(_))
+        ));
+        assert!(matches!(
+            "A=ButtonLeft".parse::<Rule>(),
+            Err(FilterError::MixedRemap(_))
+        ));
+        assert!(matches!(
+            "A=5/0s".parse::<Rule>(),
+            Err(FilterError::InvalidLimit(_))
+        ));
+    }
+
+    #[test]
+    fn test_filter_presses() {
+        let rules = ["Caps_Lock=Escape", "ButtonMiddle=drop", "BackSpace=2/s"];
+        let mut filter = Filter::new(rules.iter().map(|r| r.parse().unwrap()).collect());
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let keyboard = Source::default();
+        let duplicate = Source { seat: 0, device: 1 };
+        let pointer = Source { seat: 0, device: 2 };
+
+        // Both keyboards of the seat see the remapped key, counted once
+        assert_eq!(
+            filter.press(keyboard, CAPS_LOCK, ms(0)),
+            (Some(ESCAPE), "Caps_Lock=Escape".parse().ok())
+        );
+        assert_eq!(
+            filter.press(duplicate, CAPS_LOCK, ms(0)),
+            (Some(ESCAPE), None)
+        );
+        assert_eq!(filter.repeat(keyboard, CAPS_LOCK), Some(ESCAPE));
+        assert_eq!(filter.release(keyboard, CAPS_LOCK), Some(ESCAPE));
+        assert_eq!(filter.release(duplicate, CAPS_LOCK), Some(ESCAPE));
+
+        assert_eq!(filter.press(pointer, MIDDLE, ms(0)).0, None);
+        assert_eq!(filter.release(pointer, MIDDLE), None);
+        // Unfiltered input passes
+        assert_eq!(
+            filter.press(pointer, Input::Button(0x110), ms(0)),
+            (Some(Input::Button(0x110)), None)
+        );
+
+        for (at, passes) in [(0, true), (100, true), (200, false), (1000, true)] {
+            let (seen, _) = filter.press(keyboard, BACKSPACE, ms(at));
+            assert_eq!(seen.is_some(), passes, "press at {}ms", at);
+            assert_eq!(filter.release(keyboard, BACKSPACE), seen);
+        }
+
+        // A keyboard that leaves forgets its held keys
+        filter.press(keyboard, CAPS_LOCK, ms(2000));
+        filter.release_device(keyboard);
+        assert_eq!(filter.release(keyboard, CAPS_LOCK), Some(CAPS_LOCK));
+
+        assert_eq!(
+            filter.filtered(),
+            &BTreeMap::from([
+                ("BackSpace=2/s".to_string(), 1),
+                ("ButtonMiddle=drop".to_string(), 1),
+                ("Caps_Lock=Escape".to_string(), 2),
+            ])
+        );
+    }
+}
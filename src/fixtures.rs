@@ -0,0 +1,23 @@
+//! Values shared by the unit tests.
+
+use wl_actions::{session::CounterSnapshot, summary::Summary};
+
+pub fn counters(keys: u64, clicks: u64) -> CounterSnapshot {
+    CounterSnapshot {
+        key_presses: keys,
+        button_clicks: clicks,
+        ..CounterSnapshot::default()
+    }
+}
+
+/// A minute of `program` from `started_at` on, without pauses or laps.
+pub fn summary(program: &str, started_at: u64, keys: u64, clicks: u64) -> Summary {
+    Summary {
+        program: vec![program.to_string()],
+        started_at,
+        ended_at: started_at + 60,
+        duration_secs: 60.0,
+        counters: counters(keys, clicks),
+        ..Default::default()
+    }
+}
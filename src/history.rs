@@ -0,0 +1,458 @@
+//! Session history in a local SQLite database.
+
+use {
+    rusqlite::{Connection, OptionalExtension, Row, params},
+    std::{
+        env, fs,
+        io::{self, Write},
+        path::{Path, PathBuf},
+        time::Duration,
+    },
+    thiserror::Error,
+    wl_actions::{
+        session::CounterSnapshot,
+        summary::{self, LapSummary, Summary, format_duration},
+        weights::format_total,
+    },
+};
+
+#[derive(Debug, Error)]
+pub enum HistoryError {
+    #[error("could not create {0}")]
+    CreateDir(PathBuf, #[source] io::Error),
+    #[error("could not open {0}")]
+    Open(PathBuf, #[source] rusqlite::Error),
+    #[error("a database query failed")]
+    Query(#[from] rusqlite::Error),
+    #[error("could not encode or decode a stored session")]
+    Json(#[from] serde_json::Error),
+}
+
+/// The counters get columns of their own for querying, the whole summary is
+/// stored as JSON next to them. Sessions saved before it was added have no
+/// summary and are read back from the columns and the laps table.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS sessions (
+        id INTEGER PRIMARY KEY,
+        program TEXT NOT NULL,
+        argv TEXT NOT NULL,
+        started_at INTEGER NOT NULL,
+        ended_at INTEGER NOT NULL,
+        duration_secs REAL NOT NULL,
+        paused_secs REAL NOT NULL,
+        key_presses INTEGER NOT NULL,
+        button_clicks INTEGER NOT NULL,
+        scroll_steps INTEGER NOT NULL,
+        touch_taps INTEGER NOT NULL,
+        summary TEXT
+    );
+    CREATE INDEX IF NOT EXISTS sessions_program ON sessions (program);
+    CREATE TABLE IF NOT EXISTS laps (
+        session_id INTEGER NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+        idx INTEGER NOT NULL,
+        name TEXT,
+        duration_secs REAL NOT NULL,
+        key_presses INTEGER NOT NULL,
+        button_clicks INTEGER NOT NULL,
+        scroll_steps INTEGER NOT NULL,
+        touch_taps INTEGER NOT NULL,
+        PRIMARY KEY (session_id, idx)
+    );
+";
+
+/// Returns `$XDG_DATA_HOME/wl-actions/history.sqlite`.
+pub fn default_path() -> PathBuf {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .unwrap_or_else(env::temp_dir);
+    data_home.join("wl-actions").join("history.sqlite")
+}
+
+/// A stored session.
+#[derive(Debug)]
+pub struct Entry {
+    pub id: i64,
+    pub summary: Summary,
+}
+
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    pub fn open(path: &Path) -> Result<Self, HistoryError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| HistoryError::CreateDir(dir.to_owned(), e))?;
+        }
+        let conn = Connection::open(path).map_err(|e| HistoryError::Open(path.to_owned(), e))?;
+        Self::init(conn)
+    }
+
+    fn init(conn: Connection) -> Result<Self, HistoryError> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        let has_summary: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('sessions') WHERE name = 'summary'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_summary {
+            conn.execute_batch("ALTER TABLE sessions ADD COLUMN summary TEXT;")?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// Appends a session and returns its id.
+    pub fn insert(&mut self, summary: &Summary) -> Result<i64, HistoryError> {
+        let tx = self.conn.transaction()?;
+        let c = &summary.counters;
+        tx.execute(
+            "INSERT INTO sessions (program, argv, started_at, ended_at, duration_secs, \
+             paused_secs, key_presses, button_clicks, scroll_steps, touch_taps, summary) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                program_name(summary),
+                serde_json::to_string(&summary.program)?,
+                summary.started_at,
+                summary.ended_at,
+                summary.duration_secs,
+                summary.paused_secs,
+                c.key_presses,
+                c.button_clicks,
+                c.scroll_steps,
+                c.touch_taps,
+                serde_json::to_string(summary)?,
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        for (idx, lap) in summary.laps.iter().enumerate() {
+            let c = &lap.counters;
+            tx.execute(
+                "INSERT INTO laps (session_id, idx, name, duration_secs, key_presses, \
+                 button_clicks, scroll_steps, touch_taps) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    id,
+                    idx,
+                    lap.name,
+                    lap.duration_secs,
+                    c.key_presses,
+                    c.button_clicks,
+                    c.scroll_steps,
+                    c.touch_taps,
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Returns the most recent sessions first, optionally only those of one
+    /// program (matched by the file name of argv[0]).
+    pub fn list(&self, program: Option<&str>, limit: usize) -> Result<Vec<Entry>, HistoryError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, argv, summary, started_at, ended_at, duration_secs, paused_secs, \
+             key_presses, button_clicks, scroll_steps, touch_taps FROM sessions \
+             WHERE ?1 IS NULL OR program = ?1 ORDER BY started_at DESC, id DESC LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![program, limit as i64], session_from_row)?;
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(entry(row?)?);
+        }
+        Ok(entries)
+    }
+
+    /// Returns a session including its laps.
+    pub fn get(&self, id: i64) -> Result<Option<Entry>, HistoryError> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT id, argv, summary, started_at, ended_at, duration_secs, paused_secs, \
+                 key_presses, button_clicks, scroll_steps, touch_taps FROM sessions \
+                 WHERE id = ?1",
+                params![id],
+                session_from_row,
+            )
+            .optional()?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let stored = row.2.is_some();
+        let Entry { id, mut summary } = entry(row)?;
+        if stored {
+            return Ok(Some(Entry { id, summary }));
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT name, duration_secs, key_presses, button_clicks, scroll_steps, touch_taps \
+             FROM laps WHERE session_id = ?1 ORDER BY idx",
+        )?;
+        summary.laps = stmt
+            .query_map(params![id], |row| {
+                Ok(LapSummary {
+                    name: row.get(0)?,
+                    duration_secs: row.get(1)?,
+                    counters: counters_from_row(row, 2)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(Some(Entry { id, summary }))
+    }
+}
+
+/// The name sessions are filed under: the file name of argv[0].
+pub fn program_name(summary: &Summary) -> String {
+    summary
+        .program
+        .first()
+        .map(|program| crate::metrics::program_label(program))
+        .unwrap_or_default()
+}
+
+/// Writes one line per session.
+pub fn write_list(out: &mut impl Write, entries: &[Entry]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:>5}  {:<20}  {:>9}  {:>6}  {:>6}  {:>7}  {:>5}  {:>6}  PROGRAM",
+        "ID", "STARTED", "DURATION", "KEYS", "CLICKS", "SCROLLS", "TOUCH", "TOTAL"
+    )?;
+    for entry in entries {
+        let summary = &entry.summary;
+        let c = &summary.counters;
+        writeln!(
+            out,
+            "{:>5}  {:<20}  {:>9}  {:>6}  {:>6}  {:>7}  {:>5}  {:>6}  {}",
+            entry.id,
+            format_timestamp(summary.started_at),
+            format_duration(Duration::from_secs_f64(summary.duration_secs)),
+            c.key_presses,
+            c.button_clicks,
+            c.scroll_steps,
+            c.touch_taps,
+            format_total(summary.total_actions()),
+            summary.program.join(" ")
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes a session with its summary.
+pub fn write_entry(out: &mut impl Write, entry: &Entry) -> io::Result<()> {
+    let summary = &entry.summary;
+    writeln!(out, "Session: {}", entry.id)?;
+    writeln!(out, "Program: {}", summary.program.join(" "))?;
+    writeln!(out, "Started: {}", format_timestamp(summary.started_at))?;
+    writeln!(out, "Ended: {}", format_timestamp(summary.ended_at))?;
+    summary::write(out, summary)
+}
+
+/// Formats a Unix timestamp as `YYYY-MM-DD HH:MM UTC`.
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60
+    )
+}
+
+type SessionRow = (i64, String, Option<String>, Summary);
+
+/// Reads a session from the columns, without its program and summary, which
+/// are stored as JSON.
+fn session_from_row(row: &Row<'_>) -> rusqlite::Result<SessionRow> {
+    let summary = Summary {
+        started_at: row.get(3)?,
+        ended_at: row.get(4)?,
+        duration_secs: row.get(5)?,
+        paused_secs: row.get(6)?,
+        counters: counters_from_row(row, 7)?,
+        ..Default::default()
+    };
+    Ok((row.get(0)?, row.get(1)?, row.get(2)?, summary))
+}
+
+/// Decodes the stored summary, or the program for the sessions saved
+/// without one.
+fn entry((id, argv, stored, mut summary): SessionRow) -> Result<Entry, HistoryError> {
+    match stored {
+        Some(json) => summary = serde_json::from_str(&json)?,
+        None => summary.program = serde_json::from_str(&argv)?,
+    }
+    Ok(Entry { id, summary })
+}
+
+fn counters_from_row(row: &Row<'_>, first: usize) -> rusqlite::Result<CounterSnapshot> {
+    Ok(CounterSnapshot {
+        key_presses: row.get(first)?,
+        button_clicks: row.get(first + 1)?,
+        scroll_steps: row.get(first + 2)?,
+        touch_taps: row.get(first + 3)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::fixtures::summary,
+        wl_actions::{
+            klm::KlmCounts,
+            modality::{Modality, ModalityRun, ModalitySummary},
+            privacy::Privacy,
+            summary::SeatSummary,
+            weights::Weights,
+        },
+    };
+
+    #[test]
+    fn test_insert_and_list() {
+        let mut history = History::init(Connection::open_in_memory().unwrap()).unwrap();
+        history
+            .insert(&summary("/usr/bin/firefox", 100, 1, 0))
+            .unwrap();
+        history.insert(&summary("alacritty", 200, 2, 0)).unwrap();
+        history.insert(&summary("firefox", 300, 3, 0)).unwrap();
+
+        let all = history.list(None, 10).unwrap();
+        let keys: Vec<_> = all.iter().map(|e| e.summary.counters.key_presses).collect();
+        assert_eq!(keys, [3, 2, 1]);
+
+        let firefox = history.list(Some("firefox"), 10).unwrap();
+        let keys: Vec<_> = firefox
+            .iter()
+            .map(|e| e.summary.counters.key_presses)
+            .collect();
+        assert_eq!(keys, [3, 1]);
+
+        assert_eq!(history.list(None, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00 UTC");
+        assert_eq!(format_timestamp(951782400), "2000-02-29 00:00 UTC");
+        assert_eq!(format_timestamp(1792332180), "2026-10-18 14:03 UTC");
+    }
+
+    #[test]
+    fn test_get_with_laps() {
+        let mut history = History::init(Connection::open_in_memory().unwrap()).unwrap();
+        let mut session = summary("firefox", 100, 5, 0);
+        session.laps = vec![
+            LapSummary {
+                name: Some("login".to_string()),
+                duration_secs: 20.0,
+                counters: CounterSnapshot {
+                    key_presses: 2,
+                    ..CounterSnapshot::default()
+                },
+            },
+            LapSummary {
+                name: None,
+                duration_secs: 40.0,
+                counters: CounterSnapshot {
+                    key_presses: 3,
+                    ..CounterSnapshot::default()
+                },
+            },
+        ];
+        let id = history.insert(&session).unwrap();
+
+        let entry = history.get(id).unwrap().unwrap();
+        assert_eq!(entry.summary, session);
+        assert!(history.get(id + 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_summary_roundtrip() {
+        let mut history = History::init(Connection::open_in_memory().unwrap()).unwrap();
+        let counters = |key_presses| CounterSnapshot {
+            key_presses,
+            button_clicks: 4,
+            scroll_steps: 3,
+            touch_taps: 2,
+        };
+        let session = Summary {
+            program: vec!["firefox".to_string(), "--private-window".to_string()],
+            started_at: 100,
+            ended_at: 190,
+            duration_secs: 80.0,
+            paused_secs: 10.0,
+            counters: counters(12),
+            key_repeats: 7,
+            repeats_counted: true,
+            privacy: Privacy::Classes,
+            keys: [("letters".to_string(), 12)].into(),
+            filtered: [("drop:F1".to_string(), 2)].into(),
+            laps: vec![LapSummary {
+                name: Some("login".to_string()),
+                duration_secs: 80.0,
+                counters: counters(12),
+            }],
+            weights: Weights {
+                keys: 1.0,
+                clicks: 2.0,
+                scrolls: 0.5,
+                touch: 1.0,
+            },
+            klm: KlmCounts {
+                keystrokes: 12,
+                points: 4,
+                buttons: 4,
+                homings: 3,
+            },
+            modalities: ModalitySummary {
+                switches: 1,
+                keyboard_secs: 50.0,
+                pointer_secs: 30.0,
+                touch_secs: 0.0,
+                sequence: vec![
+                    ModalityRun {
+                        modality: Modality::Keyboard,
+                        actions: 12,
+                    },
+                    ModalityRun {
+                        modality: Modality::Pointer,
+                        actions: 7,
+                    },
+                ],
+            },
+            metrics: [("corrections.backspaces".to_string(), 3.0)].into(),
+            seats: vec![SeatSummary {
+                name: Some("seat0".to_string()),
+                counters: counters(12),
+            }],
+        };
+        let id = history.insert(&session).unwrap();
+        assert_eq!(history.get(id).unwrap().unwrap().summary, session);
+        assert_eq!(history.list(None, 1).unwrap()[0].summary, session);
+
+        // Sessions saved before the summary column only have the counters
+        history
+            .conn
+            .execute("UPDATE sessions SET summary = NULL", [])
+            .unwrap();
+        let summary = history.get(id).unwrap().unwrap().summary;
+        assert_eq!(summary.program, session.program);
+        assert_eq!(summary.counters, session.counters);
+        assert_eq!(summary.laps, session.laps);
+        assert_eq!(summary.klm, KlmCounts::default());
+    }
+}
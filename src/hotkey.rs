@@ -0,0 +1,184 @@
+use {
+    serde::Deserialize,
+    std::{collections::HashSet, fmt, str::FromStr},
+    thiserror::Error,
+    wl_actions::keys::{key_code, key_name},
+};
+
+const CTRL: u8 = 1 << 0;
+const SHIFT: u8 = 1 << 1;
+const ALT: u8 = 1 << 2;
+const SUPER: u8 = 1 << 3;
+
+/// Modifier key codes and the modifier they belong to.
+const MODIFIER_KEYS: &[(u32, u8)] = &[
+    (29, CTRL),   // KEY_LEFTCTRL
+    (97, CTRL),   // KEY_RIGHTCTRL
+    (42, SHIFT),  // KEY_LEFTSHIFT
+    (54, SHIFT),  // KEY_RIGHTSHIFT
+    (56, ALT),    // KEY_LEFTALT
+    (100, ALT),   // KEY_RIGHTALT
+    (125, SUPER), // KEY_LEFTMETA
+    (126, SUPER), // KEY_RIGHTMETA
+];
+
+const MODIFIER_NAMES: &[(&str, u8)] = &[
+    ("Ctrl", CTRL),
+    ("Control", CTRL),
+    ("Shift", SHIFT),
+    ("Alt", ALT),
+    ("Mod1", ALT),
+    ("Super", SUPER),
+    ("Logo", SUPER),
+    ("Meta", SUPER),
+    ("Mod4", SUPER),
+];
+
+#[derive(Debug, Error)]
+pub enum HotkeyError {
+    #[error("the key chord is empty")]
+    Empty,
+    #[error("unknown key `{0}`")]
+    UnknownKey(String),
+    #[error("`{0}` contains more than one non-modifier key")]
+    MultipleKeys(String),
+    #[error("`{0}` does not contain a non-modifier key")]
+    MissingKey(String),
+}
+
+/// A key chord such as `Super+F12`, matched against evdev key codes.
+///
+/// Modifiers are tracked from the pressed modifier keys rather than from
+/// `wl_keyboard::modifiers`, so matching does not depend on the keymap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Hotkey {
+    modifiers: u8,
+    key: u32,
+}
+
+impl Hotkey {
+    /// Returns whether pressing `key` while `pressed` keys are held triggers
+    /// this chord. Extra modifiers prevent a match.
+    pub fn matches(&self, key: u32, pressed: &HashSet<u32>) -> bool {
+        key == self.key && held_modifiers(pressed) == self.modifiers
+    }
+
+    /// Returns whether `key` is one of the modifier keys of this chord.
+    pub fn is_modifier_key(&self, key: u32) -> bool {
+        modifier_mask(key).is_some_and(|mask| self.modifiers & mask != 0)
+    }
+}
+
+fn modifier_mask(key: u32) -> Option<u8> {
+    MODIFIER_KEYS
+        .iter()
+        .find(|&&(code, _)| code == key)
+        .map(|&(_, mask)| mask)
+}
+
+fn held_modifiers(pressed: &HashSet<u32>) -> u8 {
+    pressed
+        .iter()
+        .filter_map(|&key| modifier_mask(key))
+        .fold(0, |acc, mask| acc | mask)
+}
+
+impl FromStr for Hotkey {
+    type Err = HotkeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Err(HotkeyError::Empty);
+        }
+        let mut modifiers = 0;
+        let mut key = None;
+        for part in s.split('+').map(str::trim) {
+            if let Some(&(_, mask)) = MODIFIER_NAMES
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(part))
+            {
+                modifiers |= mask;
+                continue;
+            }
+            let code = key_code(part).ok_or_else(|| HotkeyError::UnknownKey(part.to_string()))?;
+            if key.replace(code).is_some() {
+                return Err(HotkeyError::MultipleKeys(s.to_string()));
+            }
+        }
+        let key = key.ok_or_else(|| HotkeyError::MissingKey(s.to_string()))?;
+        Ok(Self { modifiers, key })
+    }
+}
+
+impl TryFrom<String> for Hotkey {
+    type Error = HotkeyError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, mask) in [
+            ("Ctrl", CTRL),
+            ("Shift", SHIFT),
+            ("Alt", ALT),
+            ("Super", SUPER),
+        ] {
+            if self.modifiers & mask != 0 {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match key_name(self.key) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{}", self.key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hotkey() {
+        let hotkey: Hotkey = "Super+F12".parse().unwrap();
+        assert_eq!(hotkey.to_string(), "Super+F12");
+        let hotkey: Hotkey = "ctrl + alt + m".parse().unwrap();
+        assert_eq!(hotkey.to_string(), "Ctrl+Alt+M");
+
+        assert!(matches!("".parse::<Hotkey>(), Err(HotkeyError::Empty)));
+        assert!(matches!(
+            "Super".parse::<Hotkey>(),
+            Err(HotkeyError::MissingKey(_))
+        ));
+        assert!(matches!(
+            "F1+F2".parse::<Hotkey>(),
+            Err(HotkeyError::MultipleKeys(_))
+        ));
+        assert!(matches!(
+            "Hyper+F1".parse::<Hotkey>(),
+            Err(HotkeyError::UnknownKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_hotkey_matches() {
+        let hotkey: Hotkey = "Super+F12".parse().unwrap();
+        let f12 = 88;
+
+        // Either Super key works
+        assert!(hotkey.matches(f12, &HashSet::from([125])));
+        assert!(hotkey.matches(f12, &HashSet::from([126])));
+        // Missing or extra modifiers don't
+        assert!(!hotkey.matches(f12, &HashSet::new()));
+        assert!(!hotkey.matches(f12, &HashSet::from([125, 29])));
+        // Other keys don't
+        assert!(!hotkey.matches(87, &HashSet::from([125])));
+
+        assert!(hotkey.is_modifier_key(125));
+        assert!(!hotkey.is_modifier_key(29));
+    }
+}
@@ -0,0 +1,453 @@
+//! Presses per key, exported as an SVG heatmap on a standard keyboard.
+
+use {
+    clap::ValueEnum,
+    serde::Deserialize,
+    std::{
+        collections::BTreeMap,
+        io::{self, Write},
+    },
+    wl_actions::keys::key_name,
+};
+
+/// The size of a 1u key in the heatmap, in pixels.
+const UNIT: f64 = 40.0;
+const MARGIN: f64 = 10.0;
+/// The gap between the function row and the number row.
+const ROW_GAP: f64 = 0.25;
+
+const RETURN: u32 = 28;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyboardLayout {
+    /// US style, with a wide Backslash above a one-row Return.
+    #[default]
+    Ansi,
+    /// European style, with a tall Return and an extra key next to the left
+    /// Shift.
+    Iso,
+}
+
+/// A key on the drawn keyboard, positioned in key units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct KeyCap {
+    code: u32,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+/// One row of keys as `(code, width)`, where code 0 is a gap.
+type Row = &'static [(u32, f64)];
+
+const FUNCTION_ROW: Row = &[
+    (1, 1.0),
+    (0, 1.0),
+    (59, 1.0),
+    (60, 1.0),
+    (61, 1.0),
+    (62, 1.0),
+    (0, 0.5),
+    (63, 1.0),
+    (64, 1.0),
+    (65, 1.0),
+    (66, 1.0),
+    (0, 0.5),
+    (67, 1.0),
+    (68, 1.0),
+    (87, 1.0),
+    (88, 1.0),
+    (0, 0.25),
+    (99, 1.0),
+    (70, 1.0),
+    (119, 1.0),
+];
+
+const NUMBER_ROW: Row = &[
+    (41, 1.0),
+    (2, 1.0),
+    (3, 1.0),
+    (4, 1.0),
+    (5, 1.0),
+    (6, 1.0),
+    (7, 1.0),
+    (8, 1.0),
+    (9, 1.0),
+    (10, 1.0),
+    (11, 1.0),
+    (12, 1.0),
+    (13, 1.0),
+    (14, 2.0),
+    (0, 0.25),
+    (110, 1.0),
+    (102, 1.0),
+    (104, 1.0),
+];
+
+const ANSI_TOP_ROW: Row = &[
+    (15, 1.5),
+    (16, 1.0),
+    (17, 1.0),
+    (18, 1.0),
+    (19, 1.0),
+    (20, 1.0),
+    (21, 1.0),
+    (22, 1.0),
+    (23, 1.0),
+    (24, 1.0),
+    (25, 1.0),
+    (26, 1.0),
+    (27, 1.0),
+    (43, 1.5),
+    (0, 0.25),
+    (111, 1.0),
+    (107, 1.0),
+    (109, 1.0),
+];
+
+/// The Return key starts here and spans two rows.
+const ISO_TOP_ROW: Row = &[
+    (15, 1.5),
+    (16, 1.0),
+    (17, 1.0),
+    (18, 1.0),
+    (19, 1.0),
+    (20, 1.0),
+    (21, 1.0),
+    (22, 1.0),
+    (23, 1.0),
+    (24, 1.0),
+    (25, 1.0),
+    (26, 1.0),
+    (27, 1.0),
+    (RETURN, 1.5),
+    (0, 0.25),
+    (111, 1.0),
+    (107, 1.0),
+    (109, 1.0),
+];
+
+const ANSI_HOME_ROW: Row = &[
+    (58, 1.75),
+    (30, 1.0),
+    (31, 1.0),
+    (32, 1.0),
+    (33, 1.0),
+    (34, 1.0),
+    (35, 1.0),
+    (36, 1.0),
+    (37, 1.0),
+    (38, 1.0),
+    (39, 1.0),
+    (40, 1.0),
+    (RETURN, 2.25),
+];
+
+const ISO_HOME_ROW: Row = &[
+    (58, 1.75),
+    (30, 1.0),
+    (31, 1.0),
+    (32, 1.0),
+    (33, 1.0),
+    (34, 1.0),
+    (35, 1.0),
+    (36, 1.0),
+    (37, 1.0),
+    (38, 1.0),
+    (39, 1.0),
+    (40, 1.0),
+    (43, 1.0),
+];
+
+const ANSI_SHIFT_ROW: Row = &[
+    (42, 2.25),
+    (44, 1.0),
+    (45, 1.0),
+    (46, 1.0),
+    (47, 1.0),
+    (48, 1.0),
+    (49, 1.0),
+    (50, 1.0),
+    (51, 1.0),
+    (52, 1.0),
+    (53, 1.0),
+    (54, 2.75),
+    (0, 1.25),
+    (103, 1.0),
+];
+
+const ISO_SHIFT_ROW: Row = &[
+    (42, 1.25),
+    (86, 1.0),
+    (44, 1.0),
+    (45, 1.0),
+    (46, 1.0),
+    (47, 1.0),
+    (48, 1.0),
+    (49, 1.0),
+    (50, 1.0),
+    (51, 1.0),
+    (52, 1.0),
+    (53, 1.0),
+    (54, 2.75),
+    (0, 1.25),
+    (103, 1.0),
+];
+
+const BOTTOM_ROW: Row = &[
+    (29, 1.25),
+    (125, 1.25),
+    (56, 1.25),
+    (57, 6.25),
+    (100, 1.25),
+    (126, 1.25),
+    (127, 1.25),
+    (97, 1.25),
+    (0, 0.25),
+    (105, 1.0),
+    (108, 1.0),
+    (106, 1.0),
+];
+
+impl KeyboardLayout {
+    fn rows(self) -> [Row; 6] {
+        match self {
+            KeyboardLayout::Ansi => [
+                FUNCTION_ROW,
+                NUMBER_ROW,
+                ANSI_TOP_ROW,
+                ANSI_HOME_ROW,
+                ANSI_SHIFT_ROW,
+                BOTTOM_ROW,
+            ],
+            KeyboardLayout::Iso => [
+                FUNCTION_ROW,
+                NUMBER_ROW,
+                ISO_TOP_ROW,
+                ISO_HOME_ROW,
+                ISO_SHIFT_ROW,
+                BOTTOM_ROW,
+            ],
+        }
+    }
+
+    fn keys(self) -> Vec<KeyCap> {
+        let mut keys = Vec::new();
+        for (row, spec) in self.rows().into_iter().enumerate() {
+            let y = if row == 0 { 0.0 } else { row as f64 + ROW_GAP };
+            let mut x = 0.0;
+            for &(code, width) in spec {
+                if code != 0 {
+                    let tall = self == KeyboardLayout::Iso && code == RETURN;
+                    keys.push(KeyCap {
+                        code,
+                        x,
+                        y,
+                        width,
+                        height: if tall { 2.0 } else { 1.0 },
+                    });
+                }
+                x += width;
+            }
+        }
+        keys
+    }
+}
+
+/// A short label that fits on the key.
+fn label(code: u32) -> &'static str {
+    match code {
+        1 => "Esc",
+        12 => "-",
+        13 => "=",
+        14 => "Bksp",
+        26 => "[",
+        27 => "]",
+        28 => "Enter",
+        29 | 97 => "Ctrl",
+        39 => ";",
+        40 => "'",
+        41 => "`",
+        42 | 54 => "Shift",
+        43 => "\\",
+        51 => ",",
+        52 => ".",
+        53 => "/",
+        56 | 100 => "Alt",
+        57 => "",
+        58 => "Caps",
+        70 => "ScrLk",
+        86 => "<",
+        99 => "PrtSc",
+        102 => "Home",
+        103 => "↑",
+        104 => "PgUp",
+        105 => "←",
+        106 => "→",
+        107 => "End",
+        108 => "↓",
+        109 => "PgDn",
+        110 => "Ins",
+        111 => "Del",
+        119 => "Pause",
+        125 | 126 => "Super",
+        127 => "Menu",
+        code => key_name(code).unwrap_or("?"),
+    }
+}
+
+/// Mixes from light grey for unused keys to red for the most pressed key.
+fn shade(presses: u64, max: u64) -> String {
+    if presses == 0 {
+        return "#f5f5f5".to_string();
+    }
+    let heat = presses as f64 / max as f64;
+    let mix = |from: f64, to: f64| (from + (to - from) * (0.15 + 0.85 * heat)).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        mix(245.0, 232.0),
+        mix(245.0, 41.0),
+        mix(245.0, 11.0)
+    )
+}
+
+/// Draws the keyboard with every key shaded by its share of the presses of
+/// the most pressed key, and its count underneath the label.
+pub fn write_svg(
+    out: &mut impl Write,
+    counts: &BTreeMap<u32, u64>,
+    layout: KeyboardLayout,
+) -> io::Result<()> {
+    let keys = layout.keys();
+    let max = counts.values().copied().max().unwrap_or(0);
+    let shown: u64 = keys
+        .iter()
+        .map(|key| counts.get(&key.code).copied().unwrap_or(0))
+        .sum();
+    let total: u64 = counts.values().sum();
+    let width = keys.iter().map(|key| key.x + key.width).fold(0.0, f64::max);
+    let height = keys
+        .iter()
+        .map(|key| key.y + key.height)
+        .fold(0.0, f64::max);
+
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="11" text-anchor="middle">"#,
+        w = width * UNIT + 2.0 * MARGIN,
+        h = height * UNIT + 2.0 * MARGIN + 20.0
+    )?;
+    for key in &keys {
+        let presses = counts.get(&key.code).copied().unwrap_or(0);
+        let (x, y) = (MARGIN + key.x * UNIT, MARGIN + key.y * UNIT);
+        let (w, h) = (key.width * UNIT, key.height * UNIT);
+        if key.height > 1.0 {
+            // The ISO Return key narrows by a quarter unit in its lower row
+            writeln!(
+                out,
+                r##"<polygon points="{:.0},{:.0} {:.0},{:.0} {:.0},{:.0} {:.0},{:.0} {:.0},{:.0} {:.0},{:.0}" fill="{}" stroke="#999"/>"##,
+                x + 1.0,
+                y + 1.0,
+                x + w - 1.0,
+                y + 1.0,
+                x + w - 1.0,
+                y + h - 1.0,
+                x + UNIT / 4.0 + 1.0,
+                y + h - 1.0,
+                x + UNIT / 4.0 + 1.0,
+                y + UNIT - 1.0,
+                x + 1.0,
+                y + UNIT - 1.0,
+                shade(presses, max)
+            )?;
+        } else {
+            writeln!(
+                out,
+                r##"<rect x="{:.0}" y="{:.0}" width="{:.0}" height="{:.0}" rx="4" fill="{}" stroke="#999"/>"##,
+                x + 1.0,
+                y + 1.0,
+                w - 2.0,
+                h - 2.0,
+                shade(presses, max)
+            )?;
+        }
+        writeln!(
+            out,
+            r#"<text x="{:.0}" y="{:.0}">{}</text>"#,
+            x + w / 2.0,
+            y + 16.0,
+            xml_escape(label(key.code))
+        )?;
+        if presses > 0 {
+            writeln!(
+                out,
+                r##"<text x="{:.0}" y="{:.0}" font-size="10" fill="#333">{}</text>"##,
+                x + w / 2.0,
+                y + 31.0,
+                presses
+            )?;
+        }
+    }
+    writeln!(
+        out,
+        r#"<text x="{:.0}" y="{:.0}" text-anchor="start">{} key presses{}</text>"#,
+        MARGIN,
+        MARGIN + height * UNIT + 16.0,
+        total,
+        if total > shown {
+            format!(", {} on keys not shown", total - shown)
+        } else {
+            String::new()
+        }
+    )?;
+    writeln!(out, "</svg>")
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layouts() {
+        let ansi = KeyboardLayout::Ansi.keys();
+        let iso = KeyboardLayout::Iso.keys();
+        assert!(!ansi.iter().any(|key| key.code == 86));
+        assert!(iso.iter().any(|key| key.code == 86));
+        // Every key appears once, and the main block is 15 units wide
+        for keys in [&ansi, &iso] {
+            let mut codes: Vec<_> = keys.iter().map(|key| key.code).collect();
+            codes.sort_unstable();
+            codes.dedup();
+            assert_eq!(codes.len(), keys.len());
+            let backspace = keys.iter().find(|key| key.code == 14).unwrap();
+            assert_eq!(backspace.x + backspace.width, 15.0);
+        }
+        assert_eq!(iso.len(), ansi.len() + 1);
+        let enter = iso.iter().find(|key| key.code == RETURN).unwrap();
+        assert_eq!((enter.x, enter.height), (13.5, 2.0));
+    }
+
+    #[test]
+    fn test_write_svg() {
+        // J, Down and KP_0, which is not drawn
+        let counts = BTreeMap::from([(36, 4), (108, 1), (82, 1)]);
+        let mut out = Vec::new();
+        write_svg(&mut out, &counts, KeyboardLayout::Ansi).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r##"fill="#e8290b""##));
+        assert!(svg.contains(">↓</text>"));
+        assert!(svg.contains("6 key presses, 1 on keys not shown"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+}
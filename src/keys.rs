@@ -0,0 +1,180 @@
+//! Names for the Linux evdev key codes sent in `wl_keyboard::key`.
+
+/// Key names and their evdev codes (see `linux/input-event-codes.h`).
+pub const KEYS: &[(&str, u32)] = &[
+    ("Escape", 1),
+    ("1", 2),
+    ("2", 3),
+    ("3", 4),
+    ("4", 5),
+    ("5", 6),
+    ("6", 7),
+    ("7", 8),
+    ("8", 9),
+    ("9", 10),
+    ("0", 11),
+    ("Minus", 12),
+    ("Equal", 13),
+    ("BackSpace", 14),
+    ("Tab", 15),
+    ("Q", 16),
+    ("W", 17),
+    ("E", 18),
+    ("R", 19),
+    ("T", 20),
+    ("Y", 21),
+    ("U", 22),
+    ("I", 23),
+    ("O", 24),
+    ("P", 25),
+    ("BracketLeft", 26),
+    ("BracketRight", 27),
+    ("Return", 28),
+    ("Control_L", 29),
+    ("A", 30),
+    ("S", 31),
+    ("D", 32),
+    ("F", 33),
+    ("G", 34),
+    ("H", 35),
+    ("J", 36),
+    ("K", 37),
+    ("L", 38),
+    ("Semicolon", 39),
+    ("Apostrophe", 40),
+    ("Grave", 41),
+    ("Shift_L", 42),
+    ("Backslash", 43),
+    ("Z", 44),
+    ("X", 45),
+    ("C", 46),
+    ("V", 47),
+    ("B", 48),
+    ("N", 49),
+    ("M", 50),
+    ("Comma", 51),
+    ("Period", 52),
+    ("Slash", 53),
+    ("Shift_R", 54),
+    ("KP_Multiply", 55),
+    ("Alt_L", 56),
+    ("Space", 57),
+    ("Caps_Lock", 58),
+    ("F1", 59),
+    ("F2", 60),
+    ("F3", 61),
+    ("F4", 62),
+    ("F5", 63),
+    ("F6", 64),
+    ("F7", 65),
+    ("F8", 66),
+    ("F9", 67),
+    ("F10", 68),
+    ("Num_Lock", 69),
+    ("Scroll_Lock", 70),
+    ("KP_7", 71),
+    ("KP_8", 72),
+    ("KP_9", 73),
+    ("KP_Subtract", 74),
+    ("KP_4", 75),
+    ("KP_5", 76),
+    ("KP_6", 77),
+    ("KP_Add", 78),
+    ("KP_1", 79),
+    ("KP_2", 80),
+    ("KP_3", 81),
+    ("KP_0", 82),
+    ("KP_Decimal", 83),
+    ("Less", 86),
+    ("F11", 87),
+    ("F12", 88),
+    ("KP_Enter", 96),
+    ("Control_R", 97),
+    ("KP_Divide", 98),
+    ("Print", 99),
+    ("Alt_R", 100),
+    ("Home", 102),
+    ("Up", 103),
+    ("Page_Up", 104),
+    ("Left", 105),
+    ("Right", 106),
+    ("End", 107),
+    ("Down", 108),
+    ("Page_Down", 109),
+    ("Insert", 110),
+    ("Delete", 111),
+    ("Pause", 119),
+    ("Super_L", 125),
+    ("Super_R", 126),
+    ("Menu", 127),
+    ("F13", 183),
+    ("F14", 184),
+    ("F15", 185),
+    ("F16", 186),
+    ("F17", 187),
+    ("F18", 188),
+    ("F19", 189),
+    ("F20", 190),
+    ("F21", 191),
+    ("F22", 192),
+    ("F23", 193),
+    ("F24", 194),
+];
+
+/// Alternative spellings accepted by [`key_code`].
+const ALIASES: &[(&str, &str)] = &[
+    ("Esc", "Escape"),
+    ("Enter", "Return"),
+    ("Backspace", "BackSpace"),
+    ("Del", "Delete"),
+    ("Ins", "Insert"),
+    ("PageUp", "Page_Up"),
+    ("Prior", "Page_Up"),
+    ("PageDown", "Page_Down"),
+    ("Next", "Page_Down"),
+    ("CapsLock", "Caps_Lock"),
+    ("ScrollLock", "Scroll_Lock"),
+    ("NumLock", "Num_Lock"),
+    ("SysRq", "Print"),
+];
+
+/// Returns the evdev code for a key name (case-insensitive).
+pub fn key_code(name: &str) -> Option<u32> {
+    let name = ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+        .map_or(name, |(_, canonical)| canonical);
+    KEYS.iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|&(_, code)| code)
+}
+
+/// Returns the name of an evdev code, if it is a known key.
+pub fn key_name(code: u32) -> Option<&'static str> {
+    KEYS.iter()
+        .find(|&&(_, key)| key == code)
+        .map(|&(name, _)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_code_lookup() {
+        assert_eq!(key_code("F12"), Some(88));
+        assert_eq!(key_code("f12"), Some(88));
+        assert_eq!(key_code("a"), Some(30));
+        assert_eq!(key_code("Esc"), Some(1));
+        assert_eq!(key_code("pageup"), Some(104));
+        assert_eq!(key_code("NoSuchKey"), None);
+    }
+
+    #[test]
+    fn test_key_name_roundtrip() {
+        for &(name, code) in KEYS {
+            assert_eq!(key_code(name), Some(code));
+            assert_eq!(key_name(code), Some(name));
+        }
+    }
+}
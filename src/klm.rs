@@ -0,0 +1,164 @@
+//! Keystroke-Level Model operators derived from the observed input.
+//!
+//! Only the physical operators are detected: K (keystroke), P (pointing
+//! before a click), B (mouse button) and H (homing between the keyboard and
+//! the pointer). Mental operators can't be observed and are left out, so the
+//! prediction is a lower bound for an expert user.
+
+use {
+    serde::{Deserialize, Serialize},
+    std::time::Duration,
+};
+
+/// Operator times from Card, Moran & Newell, in seconds. K is the value for
+/// an average skilled typist, B covers a press and a release.
+pub const KEYSTROKE_SECS: f64 = 0.28;
+pub const POINT_SECS: f64 = 1.1;
+pub const BUTTON_SECS: f64 = 0.2;
+pub const HOMING_SECS: f64 = 0.4;
+
+/// Pointer travel below this many surface pixels is jitter, not pointing.
+const POINT_THRESHOLD: f64 = 8.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KlmCounts {
+    pub keystrokes: u64,
+    pub points: u64,
+    pub buttons: u64,
+    pub homings: u64,
+}
+
+impl KlmCounts {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The time an expert would need for the observed operators.
+    pub fn predicted_time(&self) -> Duration {
+        Duration::from_secs_f64(
+            self.keystrokes as f64 * KEYSTROKE_SECS
+                + self.points as f64 * POINT_SECS
+                + self.buttons as f64 * BUTTON_SECS
+                + self.homings as f64 * HOMING_SECS,
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Device {
+    Keyboard,
+    Pointer,
+}
+
+/// Turns counted key presses, clicks and pointer motion into operators.
+#[derive(Debug, Default)]
+pub struct KlmTracker {
+    counts: KlmCounts,
+    /// The device used last, for detecting homing.
+    hand_on: Option<Device>,
+    /// The last pointer position, in the coordinates of the current surface.
+    position: Option<(f64, f64)>,
+    /// Pointer travel since the last click.
+    travel: f64,
+}
+
+impl KlmTracker {
+    pub fn counts(&self) -> KlmCounts {
+        self.counts
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn use_device(&mut self, device: Device) {
+        if self
+            .hand_on
+            .replace(device)
+            .is_some_and(|last| last != device)
+        {
+            self.counts.homings += 1;
+        }
+    }
+
+    pub fn key(&mut self) {
+        self.use_device(Device::Keyboard);
+        self.counts.keystrokes += 1;
+    }
+
+    /// Pointer motion in surface-local coordinates.
+    pub fn motion(&mut self, x: f64, y: f64) {
+        if let Some((last_x, last_y)) = self.position.replace((x, y)) {
+            self.travel += (x - last_x).hypot(y - last_y);
+        }
+        if self.travel >= POINT_THRESHOLD {
+            self.use_device(Device::Pointer);
+        }
+    }
+
+    /// The pointer entered a new surface. Coordinates of different surfaces
+    /// can't be compared, but getting there took pointing.
+    pub fn enter(&mut self, x: f64, y: f64) {
+        self.position = Some((x, y));
+        self.travel = self.travel.max(POINT_THRESHOLD);
+    }
+
+    pub fn click(&mut self) {
+        self.use_device(Device::Pointer);
+        if self.travel >= POINT_THRESHOLD {
+            self.counts.points += 1;
+        }
+        self.travel = 0.0;
+        self.counts.buttons += 1;
+    }
+
+    /// Scrolling needs the hand on the pointer but is not a KLM operator.
+    pub fn scroll(&mut self) {
+        self.use_device(Device::Pointer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_klm_operators() {
+        let mut klm = KlmTracker::default();
+        // Type, then reach for the mouse, point and click twice in place
+        klm.key();
+        klm.key();
+        klm.motion(10.0, 10.0);
+        klm.motion(200.0, 120.0);
+        klm.click();
+        klm.motion(201.0, 121.0);
+        klm.click();
+        // Back to the keyboard
+        klm.key();
+        assert_eq!(
+            klm.counts(),
+            KlmCounts {
+                keystrokes: 3,
+                points: 1,
+                buttons: 2,
+                homings: 2,
+            }
+        );
+        let predicted = klm.counts().predicted_time().as_secs_f64();
+        assert!((predicted - (3.0 * 0.28 + 1.1 + 2.0 * 0.2 + 2.0 * 0.4)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_enter_counts_as_pointing() {
+        let mut klm = KlmTracker::default();
+        klm.enter(5.0, 5.0);
+        klm.click();
+        klm.click();
+        assert_eq!(klm.counts().points, 1);
+        // Jitter does not move the hand to the pointer
+        klm.key();
+        klm.motion(5.0, 6.0);
+        klm.key();
+        assert_eq!(klm.counts().homings, 1);
+    }
+}
@@ -0,0 +1,139 @@
+use {
+    crate::session::CounterSnapshot,
+    std::{collections::VecDeque, time::Duration},
+};
+
+/// A segment of the session between two markers.
+#[derive(Clone, Debug)]
+pub struct Lap {
+    pub name: Option<String>,
+    pub duration: Duration,
+    pub counts: CounterSnapshot,
+}
+
+/// Records markers and turns them into per-lap counter diffs.
+///
+/// Markers are placed on the session's active time, so paused time is not
+/// part of any lap.
+#[derive(Debug)]
+pub struct LapRecorder {
+    last_mark: Duration,
+    last_snapshot: CounterSnapshot,
+    names: Vec<String>,
+    pending_names: VecDeque<String>,
+    laps: Vec<Lap>,
+}
+
+impl LapRecorder {
+    pub fn new(names: Vec<String>) -> Self {
+        Self {
+            last_mark: Duration::ZERO,
+            last_snapshot: CounterSnapshot::default(),
+            pending_names: names.iter().cloned().collect(),
+            names,
+            laps: Vec::new(),
+        }
+    }
+
+    /// Drops all laps and starts over with the first lap name.
+    pub fn reset(&mut self) {
+        *self = Self::new(std::mem::take(&mut self.names));
+    }
+
+    /// Closes the current lap at `now` and starts a new one.
+    ///
+    /// If `name` is `None`, the next name from `--lap-names` is used (if any).
+    pub fn mark(&mut self, name: Option<String>, now: Duration, snapshot: CounterSnapshot) -> Lap {
+        let name = name.or_else(|| self.pending_names.pop_front());
+        self.laps.push(Lap {
+            name,
+            duration: now.saturating_sub(self.last_mark),
+            counts: snapshot - self.last_snapshot,
+        });
+        self.last_mark = now;
+        self.last_snapshot = snapshot;
+        self.laps.last().cloned().unwrap()
+    }
+
+    /// Returns the recorded laps plus the trailing, still open lap.
+    ///
+    /// Returns an empty list if no marker was ever recorded, since a single
+    /// lap would only repeat the session totals.
+    pub fn finish(&self, now: Duration, snapshot: CounterSnapshot) -> Vec<Lap> {
+        if self.laps.is_empty() {
+            return Vec::new();
+        }
+        let mut laps = self.laps.clone();
+        laps.push(Lap {
+            name: self.pending_names.front().cloned(),
+            duration: now.saturating_sub(self.last_mark),
+            counts: snapshot - self.last_snapshot,
+        });
+        laps
+    }
+
+    pub fn len(&self) -> usize {
+        self.laps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.laps.is_empty()
+    }
+}
+
+/// ` (name)` for a named lap, nothing otherwise.
+pub fn lap_label(name: Option<&str>) -> String {
+    name.map(|name| format!(" ({})", name)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(keys: u64, clicks: u64) -> CounterSnapshot {
+        CounterSnapshot {
+            key_presses: keys,
+            button_clicks: clicks,
+            ..CounterSnapshot::default()
+        }
+    }
+
+    #[test]
+    fn test_lap_diffs() {
+        let mut laps = LapRecorder::new(Vec::new());
+
+        let lap = laps.mark(None, Duration::from_secs(5), snapshot(3, 1));
+        assert_eq!(lap.counts, snapshot(3, 1));
+        assert_eq!(lap.duration, Duration::from_secs(5));
+
+        let lap = laps.mark(None, Duration::from_secs(7), snapshot(10, 1));
+        assert_eq!(lap.counts, snapshot(7, 0));
+        assert_eq!(lap.duration, Duration::from_secs(2));
+        assert_eq!(laps.len(), 2);
+    }
+
+    #[test]
+    fn test_lap_names() {
+        let start = Duration::ZERO;
+        let names = vec![
+            "login".to_string(),
+            "search".to_string(),
+            "checkout".to_string(),
+        ];
+        let mut laps = LapRecorder::new(names);
+
+        laps.mark(None, start, snapshot(1, 0));
+        laps.mark(Some("custom".to_string()), start, snapshot(2, 0));
+
+        let all = laps.finish(start, snapshot(4, 0));
+        let names: Vec<_> = all.iter().map(|lap| lap.name.as_deref()).collect();
+        assert_eq!(names, [Some("login"), Some("custom"), Some("search")]);
+        assert_eq!(all[2].counts, snapshot(2, 0));
+    }
+
+    #[test]
+    fn test_no_markers_no_laps() {
+        let laps = LapRecorder::new(Vec::new());
+        assert!(laps.finish(Duration::ZERO, snapshot(5, 5)).is_empty());
+    }
+}
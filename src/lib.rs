@@ -0,0 +1,46 @@
+//! The counting engine of wl-actions, without the Wayland proxy and the
+//! command line.
+//!
+//! Feed [`Event`]s from any transport into a [`Session`] and it counts them
+//! the way `wl-actions` does: key and button repeats are ignored, scroll
+//! bursts are debounced, and paused time is left out. The [`Summary`] of a
+//! session is the same one `wl-actions` prints, saves and compares.
+//!
+//! ```
+//! use {
+//!     std::time::Instant,
+//!     wl_actions::{Action, Event, Session},
+//! };
+//!
+//! let now = Instant::now();
+//! let mut session = Session::new(now, Vec::new());
+//! assert_eq!(
+//!     session.handle(Event::KeyPressed { key: 30 }, now),
+//!     Some(Action::Key(30))
+//! );
+//! // A repeat of a key that is still down
+//! assert_eq!(session.handle(Event::KeyPressed { key: 30 }, now), None);
+//! assert_eq!(session.summary(now).total_actions(), 1.0);
+//! ```
+
+pub mod clock;
+pub mod corrections;
+pub mod detector;
+pub mod event;
+pub mod keys;
+pub mod klm;
+pub mod laps;
+pub mod modality;
+pub mod privacy;
+pub mod recording;
+pub mod repeats;
+pub mod session;
+pub mod summary;
+pub mod weights;
+
+pub use {
+    detector::Detector,
+    event::{Action, Event, Source},
+    session::{CounterSnapshot, Session},
+    summary::Summary,
+};
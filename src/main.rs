@@ -1,14 +1,83 @@
-use {error_reporter::Report, std::io, thiserror::Error, wl_proxy::simple::SimpleProxyError};
+use {
+    crate::{budget::BudgetError, config::ConfigError, history::HistoryError},
+    error_reporter::Report,
+    std::{io, path::PathBuf},
+    thiserror::Error,
+    wl_actions::{privacy::Privacy, recording::RecordingError},
+    wl_proxy::simple::SimpleProxyError,
+};
 
 mod actions;
+mod budget;
 mod cli;
+mod clicks;
+mod compare;
+mod config;
+mod controls;
+mod dashboard;
+mod filter;
+#[cfg(test)]
+mod fixtures;
+mod history;
+mod hotkey;
+mod keyboard;
+mod metrics;
+mod remote;
+mod socket;
+mod status_bar;
 
 #[derive(Debug, Error)]
 enum ActionsError {
+    #[error("could not load the configuration")]
+    Config(#[source] ConfigError),
     #[error("could not create a simple server")]
     CreateServer(#[source] SimpleProxyError),
+    #[error("could not register signal handlers")]
+    Signals(#[source] io::Error),
+    #[error("--control needs $XDG_RUNTIME_DIR, pass --control-socket with a private path instead")]
+    NoRuntimeDir,
+    #[error("could not create the control socket")]
+    ControlSocket(#[source] io::Error),
+    #[error("could not listen for metrics requests")]
+    MetricsListen(#[source] io::Error),
     #[error("could not spawn child")]
     SpawnChild(#[source] io::Error),
+    #[error("could not access the session history")]
+    History(#[source] HistoryError),
+    #[error("there is no session {0} in the history")]
+    NoSuchSession(i64),
+    #[error("could not read {0}")]
+    ReadSummary(PathBuf, #[source] io::Error),
+    #[error("could not parse {0}")]
+    ParseSummary(PathBuf, #[source] serde_json::Error),
+    #[error("could not write {0}")]
+    WriteSummary(PathBuf, #[source] io::Error),
+    #[error("could not create the recording {0}")]
+    CreateRecording(PathBuf, #[source] io::Error),
+    #[error("could not write the recording {0}")]
+    WriteRecording(PathBuf, #[source] io::Error),
+    #[error("could not open the recording {0}")]
+    OpenRecording(PathBuf, #[source] io::Error),
+    #[error("could not replay {0}")]
+    Replay(PathBuf, #[source] RecordingError),
+    #[error("a recording holds every key pressed, which --privacy {0} does not allow")]
+    RecordPrivacy(Privacy),
+    #[error("could not read the budget {0}")]
+    ReadBudget(PathBuf, #[source] io::Error),
+    #[error("could not parse the budget {0}")]
+    ParseBudget(PathBuf, #[source] BudgetError),
+    #[error("no limits to check, pass --assert or --budget")]
+    EmptyBudget,
+    #[error(
+        "the keyboard heatmap shows which keys were pressed, which --privacy {0} does not allow"
+    )]
+    HeatmapPrivacy(Privacy),
+    #[error("the dashboard needs stderr to be a terminal")]
+    DashboardTerminal,
+    #[error("could not write {0}")]
+    WriteExport(PathBuf, #[source] io::Error),
+    #[error("could not write to stdout")]
+    Stdout(#[source] io::Error),
     #[error("the server terminated")]
     ServerFailed(#[source] SimpleProxyError),
 }
@@ -0,0 +1,240 @@
+//! Prometheus text exposition of the counters over HTTP.
+
+use {
+    crate::{controls::Controls, remote::Remote},
+    std::{
+        collections::BTreeMap,
+        fmt::Write as _,
+        io::{self, BufRead, BufReader, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        path::Path,
+        thread,
+        time::{Duration, Instant},
+    },
+    wl_actions::{session::CounterSnapshot, weights::Weights},
+};
+
+/// Values exported on every scrape.
+pub struct Metrics {
+    pub counts: CounterSnapshot,
+    pub weights: Weights,
+    pub active: Duration,
+    pub paused: bool,
+    pub laps: usize,
+    /// The metrics of the detectors, named `<detector>.<metric>`.
+    pub detectors: BTreeMap<String, f64>,
+}
+
+impl Metrics {
+    fn collect(controls: &Controls) -> Self {
+        let now = Instant::now();
+        let session = controls.session.borrow();
+        Self {
+            counts: session.snapshot(),
+            weights: session.weights,
+            active: session.active_time(now),
+            paused: session.is_paused(),
+            laps: session.lap_count(),
+            detectors: session.metrics(now),
+        }
+    }
+}
+
+pub fn bind(addr: SocketAddr) -> io::Result<TcpListener> {
+    TcpListener::bind(addr)
+}
+
+/// Serves `GET /metrics` on a background thread.
+pub fn spawn(listener: TcpListener, remote: Remote, program: String) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let _ = serve(stream, &remote, &program);
+        }
+    });
+}
+
+fn serve(mut stream: TcpStream, remote: &Remote, program: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers, nothing in them matters here
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics" | "/")) => match remote.call(Metrics::collect) {
+            Some(metrics) => (
+                "200 OK",
+                "text/plain; version=0.0.4; charset=utf-8",
+                render(program, &metrics),
+            ),
+            None => (
+                "503 Service Unavailable",
+                "text/plain",
+                "the session has ended\n".to_string(),
+            ),
+        },
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n".to_string(),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// The name used for the `program` label: the file name of argv[0].
+pub fn program_label(program: &str) -> String {
+    Path::new(program)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| program.to_string())
+}
+
+pub fn render(program: &str, metrics: &Metrics) -> String {
+    let program = escape_label(program);
+    let counts = &metrics.counts;
+    let mut out = String::new();
+
+    let _ = writeln!(
+        out,
+        "# HELP wl_actions_actions_total Input actions counted by wl-actions."
+    );
+    let _ = writeln!(out, "# TYPE wl_actions_actions_total counter");
+    for (kind, value) in [
+        ("key", counts.key_presses),
+        ("click", counts.button_clicks),
+        ("scroll", counts.scroll_steps),
+        ("touch", counts.touch_taps),
+    ] {
+        let _ = writeln!(
+            out,
+            "wl_actions_actions_total{{program=\"{}\",kind=\"{}\"}} {}",
+            program, kind, value
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP wl_actions_weighted_total Actions weighted as in the total ({}).",
+        metrics.weights
+    );
+    let _ = writeln!(out, "# TYPE wl_actions_weighted_total counter");
+    let _ = writeln!(
+        out,
+        "wl_actions_weighted_total{{program=\"{}\"}} {}",
+        program,
+        metrics.weights.total(counts)
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP wl_actions_active_seconds_total Time spent counting, excluding pauses."
+    );
+    let _ = writeln!(out, "# TYPE wl_actions_active_seconds_total counter");
+    let _ = writeln!(
+        out,
+        "wl_actions_active_seconds_total{{program=\"{}\"}} {:.3}",
+        program,
+        metrics.active.as_secs_f64()
+    );
+
+    let _ = writeln!(out, "# HELP wl_actions_laps_total Laps recorded.");
+    let _ = writeln!(out, "# TYPE wl_actions_laps_total counter");
+    let _ = writeln!(
+        out,
+        "wl_actions_laps_total{{program=\"{}\"}} {}",
+        program, metrics.laps
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP wl_actions_paused Whether counting is paused (1) or running (0)."
+    );
+    let _ = writeln!(out, "# TYPE wl_actions_paused gauge");
+    let _ = writeln!(
+        out,
+        "wl_actions_paused{{program=\"{}\"}} {}",
+        program, metrics.paused as u8
+    );
+
+    if !metrics.detectors.is_empty() {
+        let _ = writeln!(
+            out,
+            "# HELP wl_actions_detector Metrics of the detectors, by name."
+        );
+        let _ = writeln!(out, "# TYPE wl_actions_detector gauge");
+        for (name, value) in &metrics.detectors {
+            let _ = writeln!(
+                out,
+                "wl_actions_detector{{program=\"{}\",metric=\"{}\"}} {}",
+                program,
+                escape_label(name),
+                value
+            );
+        }
+    }
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = Metrics {
+            counts: CounterSnapshot {
+                key_presses: 42,
+                button_clicks: 15,
+                scroll_steps: 8,
+                touch_taps: 3,
+            },
+            weights: Weights {
+                scrolls: 0.5,
+                ..Weights::default()
+            },
+            active: Duration::from_millis(1500),
+            paused: true,
+            laps: 2,
+            detectors: BTreeMap::from([("corrections.erases".to_string(), 4.0)]),
+        };
+        let text = render("firefox", &metrics);
+        assert!(text.contains("wl_actions_actions_total{program=\"firefox\",kind=\"key\"} 42\n"));
+        assert!(text.contains("wl_actions_actions_total{program=\"firefox\",kind=\"scroll\"} 8\n"));
+        assert!(text.contains("wl_actions_weighted_total{program=\"firefox\"} 64\n"));
+        assert!(text.contains("wl_actions_active_seconds_total{program=\"firefox\"} 1.500\n"));
+        assert!(text.contains("wl_actions_laps_total{program=\"firefox\"} 2\n"));
+        assert!(text.contains("wl_actions_paused{program=\"firefox\"} 1\n"));
+        assert!(text.contains(
+            "wl_actions_detector{program=\"firefox\",metric=\"corrections.erases\"} 4\n"
+        ));
+    }
+
+    #[test]
+    fn test_program_label() {
+        assert_eq!(program_label("/usr/bin/firefox"), "firefox");
+        assert_eq!(program_label("alacritty"), "alacritty");
+        assert_eq!(escape_label("a\"b\\c\nd"), r#"a\"b\\c\nd"#);
+    }
+}
@@ -0,0 +1,129 @@
+//! Which input device the user is on, and how often they switch.
+
+use {
+    serde::{Deserialize, Serialize},
+    std::time::Duration,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Modality {
+    Keyboard,
+    Pointer,
+    Touch,
+}
+
+impl Modality {
+    fn index(self) -> usize {
+        match self {
+            Modality::Keyboard => 0,
+            Modality::Pointer => 1,
+            Modality::Touch => 2,
+        }
+    }
+}
+
+/// A stretch of consecutive actions on one device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModalityRun {
+    pub modality: Modality,
+    pub actions: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModalitySummary {
+    pub switches: u64,
+    pub keyboard_secs: f64,
+    pub pointer_secs: f64,
+    pub touch_secs: f64,
+    /// The runs in order.
+    pub sequence: Vec<ModalityRun>,
+}
+
+impl ModalitySummary {
+    pub fn is_empty(&self) -> bool {
+        self.sequence.is_empty()
+    }
+}
+
+/// Tracks the modality of every counted action.
+///
+/// The time from one run's first action to the next run's first action is
+/// spent in that run's modality. Times are active session times, so pauses
+/// are excluded.
+#[derive(Debug, Default)]
+pub struct ModalityTracker {
+    runs: Vec<ModalityRun>,
+    run_started: Duration,
+    time: [Duration; 3],
+}
+
+impl ModalityTracker {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn record(&mut self, modality: Modality, now: Duration) {
+        match self.runs.last_mut() {
+            Some(run) if run.modality == modality => run.actions += 1,
+            last => {
+                if let Some(run) = last {
+                    self.time[run.modality.index()] += now.saturating_sub(self.run_started);
+                }
+                self.runs.push(ModalityRun {
+                    modality,
+                    actions: 1,
+                });
+                self.run_started = now;
+            }
+        }
+    }
+
+    /// The summary at `now`, counting the current run up to then.
+    pub fn summary(&self, now: Duration) -> ModalitySummary {
+        let mut time = self.time;
+        if let Some(run) = self.runs.last() {
+            time[run.modality.index()] += now.saturating_sub(self.run_started);
+        }
+        ModalitySummary {
+            switches: self.runs.len().saturating_sub(1) as u64,
+            keyboard_secs: time[0].as_secs_f64(),
+            pointer_secs: time[1].as_secs_f64(),
+            touch_secs: time[2].as_secs_f64(),
+            sequence: self.runs.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modality_switches() {
+        let secs = Duration::from_secs;
+        let mut tracker = ModalityTracker::default();
+        tracker.record(Modality::Keyboard, secs(2));
+        tracker.record(Modality::Keyboard, secs(5));
+        tracker.record(Modality::Pointer, secs(10));
+        tracker.record(Modality::Keyboard, secs(13));
+        tracker.record(Modality::Touch, secs(20));
+
+        let summary = tracker.summary(secs(30));
+        assert_eq!(summary.switches, 3);
+        assert_eq!(summary.keyboard_secs, 8.0 + 7.0);
+        assert_eq!(summary.pointer_secs, 3.0);
+        assert_eq!(summary.touch_secs, 10.0);
+        assert_eq!(
+            summary.sequence[0],
+            ModalityRun {
+                modality: Modality::Keyboard,
+                actions: 2,
+            }
+        );
+        assert_eq!(summary.sequence.len(), 4);
+
+        tracker.reset();
+        assert!(tracker.summary(secs(40)).is_empty());
+    }
+}
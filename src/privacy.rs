@@ -0,0 +1,153 @@
+//! How much of the identity of pressed keys ends up in detailed output.
+//!
+//! Per-key counts can reveal what was typed, passwords included. The privacy
+//! level decides how keys are labelled in the summary and whether the
+//! keyboard heatmap can be drawn at all. Key presses always count towards
+//! the totals.
+
+use {
+    crate::keys::key_name,
+    serde::{Deserialize, Serialize},
+    std::{
+        fmt,
+        hash::{BuildHasher, RandomState},
+        str::FromStr,
+    },
+    thiserror::Error,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Privacy {
+    /// Keys are labelled by name.
+    #[default]
+    Off,
+    /// Keys are labelled by a hash of their code, salted per session, so
+    /// counts can be told apart within a session but not looked up.
+    Hash,
+    /// Keys are only labelled by a coarse class such as `letters`.
+    Classes,
+    /// No per-key detail is kept.
+    Redact,
+}
+
+#[derive(Debug, Error)]
+#[error("unknown privacy level `{0}`, expected off, hash, classes or redact")]
+pub struct PrivacyError(String);
+
+impl FromStr for Privacy {
+    type Err = PrivacyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "off" => Ok(Self::Off),
+            "hash" => Ok(Self::Hash),
+            "classes" => Ok(Self::Classes),
+            "redact" => Ok(Self::Redact),
+            other => Err(PrivacyError(other.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Privacy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::Hash => "hash",
+            Self::Classes => "classes",
+            Self::Redact => "redact",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KeyClass {
+    Letters,
+    Digits,
+    Navigation,
+    Editing,
+    Modifiers,
+    Other,
+}
+
+impl KeyClass {
+    pub fn of(code: u32) -> Self {
+        match code {
+            16..=25 | 30..=38 | 44..=50 => Self::Letters,
+            2..=11 | 71..=73 | 75..=77 | 79..=82 => Self::Digits,
+            15 | 102..=109 => Self::Navigation,
+            14 | 28 | 57 | 96 | 110 | 111 => Self::Editing,
+            29 | 42 | 54 | 56 | 58 | 97 | 100 | 125 | 126 => Self::Modifiers,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Letters => "letters",
+            Self::Digits => "digits",
+            Self::Navigation => "navigation",
+            Self::Editing => "editing",
+            Self::Modifiers => "modifiers",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// Labels keys according to a privacy level.
+#[derive(Debug)]
+pub struct KeyLabeler {
+    privacy: Privacy,
+    salt: RandomState,
+}
+
+impl KeyLabeler {
+    /// Draws a new salt for hashed labels.
+    pub fn new(privacy: Privacy) -> Self {
+        Self {
+            privacy,
+            salt: RandomState::new(),
+        }
+    }
+
+    pub fn privacy(&self) -> Privacy {
+        self.privacy
+    }
+
+    /// The label of `code`, or `None` if keys are redacted.
+    pub fn label(&self, code: u32) -> Option<String> {
+        match self.privacy {
+            Privacy::Off => Some(
+                key_name(code)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("key{}", code)),
+            ),
+            Privacy::Hash => Some(format!("{:08x}", self.salt.hash_one(code) as u32)),
+            Privacy::Classes => Some(KeyClass::of(code).name().to_string()),
+            Privacy::Redact => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels() {
+        let label = |privacy, code| KeyLabeler::new(privacy).label(code);
+        assert_eq!(label(Privacy::Off, 30).as_deref(), Some("A"));
+        assert_eq!(label(Privacy::Classes, 30).as_deref(), Some("letters"));
+        assert_eq!(label(Privacy::Classes, 103).as_deref(), Some("navigation"));
+        assert_eq!(label(Privacy::Redact, 30), None);
+
+        // Stable within a session, not a key name
+        let labeler = KeyLabeler::new(Privacy::Hash);
+        assert_eq!(labeler.label(30), labeler.label(30));
+        assert_ne!(labeler.label(30), labeler.label(31));
+        assert_ne!(labeler.label(30).as_deref(), Some("A"));
+
+        assert_eq!("classes".parse::<Privacy>().unwrap(), Privacy::Classes);
+        assert!("some".parse::<Privacy>().is_err());
+    }
+}
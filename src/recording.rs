@@ -0,0 +1,455 @@
+//! Records the event stream of a session and replays it into a new one.
+//!
+//! A recording has one JSON object per line: a `start` record with the
+//! settings of the session, then every call that changed the session,
+//! stamped with the time since the start, and an `end` record. Replaying it
+//! calls the same [`Session`] methods at the same times, so the summary comes
+//! out the same without the program or a display, e.g. to check a budget in
+//! a headless CI job.
+//!
+//! While a password is being typed, keys are recorded as placeholder codes
+//! from [`PLACEHOLDER_KEYS`] on, a new one for every press, so a recording
+//! never reveals a password. The modifiers, BackSpace and Delete keep their
+//! codes, so the estimated repeats and the corrections still come out the
+//! same.
+
+use {
+    crate::{
+        detector::Detector,
+        event::{Event, Source},
+        privacy::Privacy,
+        repeats::RepeatInfo,
+        session::Session,
+        summary::Summary,
+        weights::Weights,
+    },
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::{BTreeMap, HashMap},
+        fmt,
+        io::{self, BufRead, Write},
+        time::{Duration, Instant, UNIX_EPOCH},
+    },
+    thiserror::Error,
+};
+
+/// The first code used for keys pressed while a password is typed, above
+/// any evdev code.
+pub const PLACEHOLDER_KEYS: u32 = 0x10000;
+
+/// Keys that keep their code while a password is typed: the modifiers and
+/// the locks, BackSpace and Delete.
+const UNMASKED: [u32; 13] = [14, 29, 42, 54, 56, 58, 69, 70, 97, 100, 111, 125, 126];
+
+/// One line of a recording, at `at_us` microseconds after the start.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub at_us: u64,
+    #[serde(flatten)]
+    pub record: Record,
+}
+
+/// A call that changed the session.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    /// The settings of the session, always first.
+    Start {
+        program: Vec<String>,
+        /// Unix timestamp in seconds.
+        started_at: u64,
+        scroll_debounce_ms: u64,
+        count_repeats: bool,
+        weights: Weights,
+        privacy: Privacy,
+    },
+    Event {
+        source: Source,
+        event: Event,
+    },
+    /// A key held without counting, see [`Session::hold_key`].
+    Hold {
+        source: Source,
+        key: u32,
+    },
+    RepeatInfo {
+        source: Source,
+        rate: u32,
+        delay_ms: u64,
+    },
+    Sensitive {
+        input: u64,
+        sensitive: bool,
+    },
+    SeatName {
+        seat: u32,
+        name: String,
+    },
+    /// A press withheld or remapped by an input filter rule.
+    Filtered {
+        rule: String,
+    },
+    /// The counting rules changed, see [`Session::set_counting`].
+    Counting {
+        scroll_debounce_ms: u64,
+        count_repeats: bool,
+    },
+    Pause,
+    Resume,
+    Reset,
+    /// A lap was marked, with the name it got.
+    Lap {
+        name: Option<String>,
+    },
+    /// The end of the session, with the weights it ended with.
+    End {
+        weights: Weights,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum RecordingError {
+    #[error("could not read the recording")]
+    Read(#[source] io::Error),
+    #[error("line {0} is not a valid record")]
+    Parse(usize, #[source] serde_json::Error),
+    #[error("the recording does not begin with a start record")]
+    NoStart,
+}
+
+/// Writes the records of a session as JSON lines.
+pub struct Recorder {
+    out: Box<dyn Write + Send>,
+    start: Instant,
+    /// The time of the last record, for the records of calls that do not
+    /// take one.
+    last_at_us: u64,
+    /// The placeholder of each key pressed while a password was typed, by
+    /// seat and key, until it is released.
+    placeholders: HashMap<(u32, u32), u32>,
+    next_placeholder: u32,
+    /// The first write that failed; nothing is written after it.
+    error: Option<io::Error>,
+}
+
+impl Recorder {
+    /// Records to `out`, timing the records from `start`.
+    pub fn new(out: Box<dyn Write + Send>, start: Instant) -> Self {
+        Self {
+            out,
+            start,
+            last_at_us: 0,
+            placeholders: HashMap::new(),
+            next_placeholder: PLACEHOLDER_KEYS,
+            error: None,
+        }
+    }
+
+    /// Writes a record of a call at `now`.
+    pub fn write(&mut self, record: Record, now: Instant) {
+        self.last_at_us = now.saturating_duration_since(self.start).as_micros() as u64;
+        self.write_untimed(record);
+    }
+
+    /// Writes a record of a call that does not depend on the time, at the
+    /// time of the last one.
+    pub fn write_untimed(&mut self, record: Record) {
+        if self.error.is_some() {
+            return;
+        }
+        let entry = Entry {
+            at_us: self.last_at_us,
+            record,
+        };
+        let line = serde_json::to_string(&entry).unwrap();
+        if let Err(err) = writeln!(self.out, "{}", line) {
+            self.error = Some(err);
+        }
+    }
+
+    /// The code to record for `key` on `seat`. A key that goes down while
+    /// `masked` gets a new placeholder, which it keeps until
+    /// [`Recorder::forget`].
+    pub fn key(&mut self, seat: u32, key: u32, masked: bool) -> u32 {
+        if let Some(&placeholder) = self.placeholders.get(&(seat, key)) {
+            return placeholder;
+        }
+        if !masked || UNMASKED.contains(&key) {
+            return key;
+        }
+        let placeholder = self.next_placeholder;
+        self.next_placeholder += 1;
+        self.placeholders.insert((seat, key), placeholder);
+        placeholder
+    }
+
+    /// Drops the placeholder of a key that went up on every device of
+    /// `seat`.
+    pub fn forget(&mut self, seat: u32, key: u32) {
+        self.placeholders.remove(&(seat, key));
+    }
+
+    /// Writes the end record and flushes, returning the first error.
+    pub fn finish(mut self, weights: Weights, now: Instant) -> io::Result<()> {
+        self.write(Record::End { weights }, now);
+        match self.error {
+            Some(err) => Err(err),
+            None => self.out.flush(),
+        }
+    }
+}
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder")
+            .field("start", &self.start)
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Replays a recording into a new session observed by `detectors`, and
+/// returns its summary. A recording cut short, e.g. by a crash, is replayed
+/// up to its last record.
+pub fn replay(
+    input: impl BufRead,
+    detectors: Vec<Box<dyn Detector>>,
+) -> Result<Summary, RecordingError> {
+    let start = Instant::now();
+    let mut lines = input.lines().enumerate();
+    let parse = |lines: &mut dyn Iterator<Item = (usize, io::Result<String>)>| {
+        let Some((i, line)) = lines.next() else {
+            return Ok(None);
+        };
+        let line = line.map_err(RecordingError::Read)?;
+        serde_json::from_str::<Entry>(&line)
+            .map(Some)
+            .map_err(|e| RecordingError::Parse(i + 1, e))
+    };
+
+    let Some(Entry {
+        record:
+            Record::Start {
+                program,
+                started_at,
+                scroll_debounce_ms,
+                count_repeats,
+                weights,
+                privacy,
+            },
+        ..
+    }) = parse(&mut lines)?
+    else {
+        return Err(RecordingError::NoStart);
+    };
+    let mut session = Session::new(start, Vec::new());
+    session.program = program;
+    session.started_at = UNIX_EPOCH + Duration::from_secs(started_at);
+    session.scroll_debounce = Duration::from_millis(scroll_debounce_ms);
+    session.count_repeats = count_repeats;
+    session.weights = weights;
+    session.set_privacy(privacy);
+    for detector in detectors {
+        session.add_detector(detector);
+    }
+
+    let mut filtered = BTreeMap::new();
+    let mut end = start;
+    while let Some(entry) = parse(&mut lines)? {
+        let now = start + Duration::from_micros(entry.at_us);
+        end = end.max(now);
+        match entry.record {
+            Record::Start { .. } => {}
+            Record::Event { source, event } => {
+                session.handle_from(source, event, now);
+            }
+            Record::Hold { source, key } => {
+                session.hold_key(source, key);
+            }
+            Record::RepeatInfo {
+                source,
+                rate,
+                delay_ms,
+            } => session.set_repeat_info(
+                source,
+                RepeatInfo {
+                    rate,
+                    delay: Duration::from_millis(delay_ms),
+                },
+            ),
+            Record::Sensitive { input, sensitive } => session.set_sensitive(input, sensitive),
+            Record::SeatName { seat, name } => session.set_seat_name(seat, &name),
+            Record::Filtered { rule } => *filtered.entry(rule).or_default() += 1,
+            Record::Counting {
+                scroll_debounce_ms,
+                count_repeats,
+            } => session.set_counting(Duration::from_millis(scroll_debounce_ms), count_repeats),
+            Record::Pause => {
+                session.pause(now);
+            }
+            Record::Resume => {
+                session.resume(now);
+            }
+            Record::Reset => {
+                session.reset(now);
+                filtered.clear();
+            }
+            Record::Lap { name } => {
+                session.mark_lap(name, now);
+            }
+            Record::End { weights } => {
+                session.weights = weights;
+                break;
+            }
+        }
+    }
+    let mut summary = session.summary(end);
+    summary.filtered = filtered;
+    summary.ended_at = started_at + (end - start).as_secs();
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::detector,
+        std::sync::{Arc, Mutex},
+    };
+
+    /// A buffer that stays readable after the session took its writer.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let keyboard = Source { seat: 0, device: 0 };
+        let pointer = Source { seat: 0, device: 1 };
+        let mut session = Session::new(start, vec!["login".to_string()]);
+        session.program = vec!["firefox".to_string()];
+        session.count_repeats = true;
+        for detector in detector::builtin() {
+            session.add_detector(detector);
+        }
+        let out = Shared::default();
+        session.record_to(Box::new(out.clone()), start);
+
+        session.set_seat_name(0, "seat0");
+        session.set_repeat_info(
+            keyboard,
+            RepeatInfo {
+                rate: 25,
+                delay: Duration::from_millis(600),
+            },
+        );
+        session.handle_from(keyboard, Event::KeyPressed { key: 30 }, ms(10));
+        session.handle_from(keyboard, Event::KeyReleased { key: 30 }, ms(50));
+        // A held BackSpace, estimated as repeats
+        session.handle_from(keyboard, Event::KeyPressed { key: 14 }, ms(100));
+        session.handle_from(keyboard, Event::KeyReleased { key: 14 }, ms(1300));
+        session.handle_from(pointer, Event::PointerEnter { x: 1.0, y: 2.0 }, ms(1400));
+        session.handle_from(pointer, Event::ButtonPressed { button: 272 }, ms(1500));
+        session.handle_from(pointer, Event::ButtonReleased { button: 272 }, ms(1550));
+        session.mark_lap(None, ms(1600));
+        session.handle_from(pointer, Event::Scroll, ms(1700));
+        session.handle_from(pointer, Event::Scroll, ms(1750));
+        // A profile picked by app_id stops debouncing
+        session.set_counting(Duration::ZERO, true);
+        session.handle_from(pointer, Event::Scroll, ms(1760));
+        session.record_filtered("ButtonMiddle=drop".to_string(), ms(1800));
+        session.pause(ms(2000));
+        // Already paused, so not recorded
+        assert!(!session.pause(ms(2050)));
+        session.handle_from(keyboard, Event::KeyPressed { key: 31 }, ms(2100));
+        session.resume(ms(3000));
+        session.handle_from(keyboard, Event::KeyReleased { key: 31 }, ms(3100));
+        // Typing a password, with the field focused while A is down
+        session.handle_from(keyboard, Event::KeyPressed { key: 30 }, ms(3200));
+        session.set_sensitive(1, true);
+        session.handle_from(keyboard, Event::KeyPressed { key: 32 }, ms(3300));
+        session.handle_from(keyboard, Event::KeyReleased { key: 32 }, ms(3350));
+        session.handle_from(keyboard, Event::KeyPressed { key: 32 }, ms(3400));
+        session.handle_from(keyboard, Event::KeyReleased { key: 30 }, ms(3450));
+        session.handle_from(keyboard, Event::KeyPressed { key: 14 }, ms(3500));
+        session.set_sensitive(1, false);
+        session.handle_from(keyboard, Event::KeyReleased { key: 32 }, ms(3550));
+        session.handle_from(keyboard, Event::KeyReleased { key: 14 }, ms(3600));
+        assert!(session.sync_keys(keyboard, &[33], ms(3700)).is_empty());
+        session.weights.clicks = 2.0;
+        session.finish_recording(ms(4000)).unwrap();
+        let mut expected = session.summary(ms(4000));
+        expected.filtered = BTreeMap::from([("ButtonMiddle=drop".to_string(), 1)]);
+
+        let recording = out.0.lock().unwrap().clone();
+        let text = String::from_utf8(recording.clone()).unwrap();
+        assert!(text.starts_with(r#"{"at_us":0,"type":"start","program":["firefox"]"#));
+        // Only the placeholders show the keys of the password
+        let keys: Vec<u32> = text
+            .lines()
+            .filter_map(
+                |line| match serde_json::from_str::<Entry>(line).unwrap().record {
+                    Record::Event {
+                        event: Event::KeyPressed { key },
+                        ..
+                    } => Some(key),
+                    _ => None,
+                },
+            )
+            .collect();
+        assert_eq!(
+            keys,
+            [30, 14, 31, 30, PLACEHOLDER_KEYS, PLACEHOLDER_KEYS + 1, 14]
+        );
+        assert_eq!(text.matches(r#""type":"pause""#).count(), 1);
+
+        let summary = replay(recording.as_slice(), detector::builtin()).unwrap();
+        assert_eq!(summary.laps[0].name.as_deref(), Some("login"));
+        assert_eq!(summary.metrics["corrections.erases"], 18.0);
+        assert_eq!(summary.weights.clicks, 2.0);
+        assert_eq!(summary.counters.scroll_steps, 2);
+        expected.ended_at = summary.ended_at;
+        assert_eq!(summary, expected);
+    }
+
+    #[test]
+    fn test_replay_errors() {
+        let replay = |text: &str| replay(text.as_bytes(), Vec::new());
+        assert!(matches!(replay(""), Err(RecordingError::NoStart)));
+        assert!(matches!(
+            replay(r#"{"at_us":0,"type":"pause"}"#),
+            Err(RecordingError::NoStart)
+        ));
+        let start = concat!(
+            r#"{"at_us":0,"type":"start","program":["foot"],"started_at":100,"#,
+            r#""scroll_debounce_ms":100,"count_repeats":false,"weights":{},"privacy":"off"}"#,
+            "\n"
+        );
+        assert!(matches!(
+            replay(&format!("{}{{\"at_us\":5}}\n", start)),
+            Err(RecordingError::Parse(2, _))
+        ));
+
+        // Cut short without an end record
+        let event = concat!(
+            r#"{"at_us":2000000,"type":"event","source":{"seat":0,"device":0},"#,
+            r#""event":{"kind":"key_pressed","key":30}}"#
+        );
+        let summary = replay(&format!("{}{}\n", start, event)).unwrap();
+        assert_eq!(summary.program, ["foot"]);
+        assert_eq!(summary.counters.key_presses, 1);
+        assert_eq!(summary.duration_secs, 2.0);
+        assert_eq!(summary.ended_at, 102);
+    }
+}
@@ -0,0 +1,127 @@
+//! Runs code on the proxy thread on behalf of the other threads.
+//!
+//! The session lives on the proxy thread and is used there without locks.
+//! The signal handlers, the control socket and the metrics endpoint queue
+//! jobs instead, then wake the proxy with a `wl_display.sync` round trip over
+//! a connection of their own: the proxy runs the queued jobs when it sees the
+//! request, so they have run by the time the `done` event arrives.
+
+use {
+    crate::controls::Controls,
+    std::{
+        env,
+        ffi::OsStr,
+        io::{self, Read, Write},
+        os::unix::net::UnixStream,
+        path::{Path, PathBuf},
+        sync::{
+            Arc, Mutex,
+            mpsc::{self, Receiver, Sender},
+        },
+        time::Duration,
+    },
+};
+
+pub type Job = Box<dyn FnOnce(&Controls) + Send>;
+
+const WL_DISPLAY: u32 = 1;
+const WL_DISPLAY_SYNC: u32 = 0;
+
+/// How long a caller waits for the round trip before giving up.
+const CALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A handle for queueing jobs, usable from any thread.
+#[derive(Clone)]
+pub struct Remote {
+    jobs: Sender<Job>,
+    display: PathBuf,
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+/// Returns the handle for the other threads and the queue the proxy thread
+/// runs, for the proxy listening on `display`.
+pub fn channel(display: &OsStr) -> (Remote, Receiver<Job>) {
+    let (jobs, queue) = mpsc::channel();
+    let remote = Remote {
+        jobs,
+        display: socket_path(display),
+        waker: Arc::new(Mutex::new(None)),
+    };
+    (remote, queue)
+}
+
+/// Resolves a display name the way libwayland does.
+fn socket_path(display: &OsStr) -> PathBuf {
+    let display = Path::new(display);
+    if display.is_absolute() {
+        return display.to_owned();
+    }
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(display)
+}
+
+impl Remote {
+    /// Runs `f` on the proxy thread and returns its result, or `None` if the
+    /// proxy is no longer running or did not answer in time.
+    pub fn call<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Controls) -> R + Send + 'static,
+    ) -> Option<R> {
+        let (result, receiver) = mpsc::channel();
+        let job: Job = Box::new(move |controls| {
+            let _ = result.send(f(controls));
+        });
+        self.jobs.send(job).ok()?;
+        self.wake().ok()?;
+        receiver.try_recv().ok()
+    }
+
+    fn wake(&self) -> io::Result<()> {
+        let mut waker = self.waker.lock().unwrap();
+        if waker.is_none() {
+            let stream = UnixStream::connect(&self.display)?;
+            stream.set_read_timeout(Some(CALL_TIMEOUT))?;
+            stream.set_write_timeout(Some(CALL_TIMEOUT))?;
+            *waker = Some(Waker { stream, next_id: 2 });
+        }
+        let result = waker.as_mut().unwrap().roundtrip();
+        if result.is_err() {
+            // Out of step after a timeout, connect again next time
+            *waker = None;
+        }
+        result
+    }
+}
+
+/// A bare Wayland client connection to the proxy.
+struct Waker {
+    stream: UnixStream,
+    next_id: u32,
+}
+
+impl Waker {
+    fn roundtrip(&mut self) -> io::Result<()> {
+        let callback = self.next_id;
+        self.next_id += 1;
+        let mut request = Vec::with_capacity(12);
+        request.extend_from_slice(&WL_DISPLAY.to_ne_bytes());
+        request.extend_from_slice(&((12 << 16) | WL_DISPLAY_SYNC).to_ne_bytes());
+        request.extend_from_slice(&callback.to_ne_bytes());
+        self.stream.write_all(&request)?;
+
+        // Skip `delete_id` and anything else until the callback is done
+        loop {
+            let mut header = [0; 8];
+            self.stream.read_exact(&mut header)?;
+            let object = u32::from_ne_bytes(header[..4].try_into().unwrap());
+            let size = u32::from_ne_bytes(header[4..].try_into().unwrap()) >> 16;
+            let mut body = vec![0; (size as usize).saturating_sub(8)];
+            self.stream.read_exact(&mut body)?;
+            if object == callback {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Estimates the key repeats that clients generate themselves.
+//!
+//! Before `wl_keyboard` version 10 the compositor only announces the repeat
+//! rate and delay, and each client repeats held keys on its own, so the
+//! repeats never pass through the proxy. They are estimated from how long a
+//! key was held instead. Devices that receive `repeated` key events from the
+//! compositor are counted from those events and not estimated.
+
+use {
+    crate::event::Source,
+    std::{
+        collections::{HashMap, HashSet},
+        time::{Duration, Instant},
+    },
+};
+
+/// Keys that xkb does not repeat: the modifiers and the locks.
+const NON_REPEATING: [u32; 11] = [29, 42, 54, 56, 58, 69, 70, 97, 100, 125, 126];
+
+/// The repeat settings from `wl_keyboard.repeat_info`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RepeatInfo {
+    /// Repeats per second; 0 disables repeating.
+    pub rate: u32,
+    pub delay: Duration,
+}
+
+impl RepeatInfo {
+    /// The repeats a client generates for a key held for `held`.
+    pub fn repeats(&self, held: Duration) -> u64 {
+        if self.rate == 0 || held < self.delay {
+            return 0;
+        }
+        let repeating = held - self.delay;
+        1 + (repeating.as_secs_f64() * self.rate as f64) as u64
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RepeatTracker {
+    info: HashMap<Source, RepeatInfo>,
+    /// Devices that get `repeated` events from the compositor.
+    compositor_repeats: HashSet<Source>,
+    /// The device that pressed each held key first, by seat and key, and
+    /// when. Only its repeats count, so duplicate keyboards count once.
+    held: HashMap<(u32, u32), (u64, Instant)>,
+}
+
+impl RepeatTracker {
+    pub fn set_info(&mut self, source: Source, info: RepeatInfo) {
+        self.info.insert(source, info);
+    }
+
+    pub fn press(&mut self, source: Source, key: u32, now: Instant) {
+        if NON_REPEATING.contains(&key) {
+            return;
+        }
+        self.held
+            .entry((source.seat, key))
+            .or_insert((source.device, now));
+    }
+
+    /// Returns the estimated repeats of `key`, released on `source` at
+    /// `now`.
+    pub fn release(&mut self, source: Source, key: u32, now: Instant) -> u64 {
+        let Some(&(device, pressed_at)) = self.held.get(&(source.seat, key)) else {
+            return 0;
+        };
+        if device != source.device {
+            return 0;
+        }
+        self.held.remove(&(source.seat, key));
+        if self.compositor_repeats.contains(&source) {
+            return 0;
+        }
+        self.info
+            .get(&source)
+            .map(|info| info.repeats(now.saturating_duration_since(pressed_at)))
+            .unwrap_or(0)
+    }
+
+    /// Notes a `repeated` event from the compositor, which then is not
+    /// estimated for `source`. Returns whether it counts.
+    pub fn repeated(&mut self, source: Source, key: u32) -> bool {
+        self.compositor_repeats.insert(source);
+        self.held
+            .get(&(source.seat, key))
+            .is_some_and(|&(device, _)| device == source.device)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimated_repeats() {
+        let info = RepeatInfo {
+            rate: 25,
+            delay: Duration::from_millis(600),
+        };
+        assert_eq!(info.repeats(Duration::from_millis(500)), 0);
+        assert_eq!(info.repeats(Duration::from_millis(600)), 1);
+        assert_eq!(info.repeats(Duration::from_millis(2200)), 41);
+
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let keyboard = Source::default();
+        let mut tracker = RepeatTracker::default();
+        tracker.set_info(keyboard, info);
+        // Backspace held for 2.2s
+        tracker.press(keyboard, 14, ms(0));
+        assert_eq!(tracker.release(keyboard, 14, ms(2200)), 41);
+        // Modifiers do not repeat
+        tracker.press(keyboard, 29, ms(0));
+        assert_eq!(tracker.release(keyboard, 29, ms(2200)), 0);
+
+        // A second keyboard of the seat sees the same key
+        let duplicate = Source { seat: 0, device: 1 };
+        tracker.set_info(duplicate, info);
+        tracker.press(keyboard, 14, ms(3000));
+        tracker.press(duplicate, 14, ms(3000));
+        assert_eq!(tracker.release(duplicate, 14, ms(5000)), 0);
+        assert!(tracker.repeated(keyboard, 14));
+        assert!(!tracker.repeated(duplicate, 14));
+        // The compositor sends the repeats now
+        assert_eq!(tracker.release(keyboard, 14, ms(5000)), 0);
+    }
+}
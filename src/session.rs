@@ -0,0 +1,876 @@
+//! The counting engine: turns a stream of input events into counters, laps
+//! and a summary.
+
+use {
+    crate::{
+        clock::SessionClock,
+        detector::Detector,
+        event::{Action, Event, Source},
+        klm::{KlmCounts, KlmTracker},
+        laps::{Lap, LapRecorder},
+        modality::{Modality, ModalitySummary, ModalityTracker},
+        privacy::{KeyLabeler, Privacy},
+        recording::{Record, Recorder},
+        repeats::{RepeatInfo, RepeatTracker},
+        summary::{LapSummary, SeatSummary, Summary},
+        weights::Weights,
+    },
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::{BTreeMap, HashMap, HashSet},
+        io::{self, Write},
+        ops::{AddAssign, Sub},
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// Scroll events closer together than this count as one step.
+pub const DEFAULT_SCROLL_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// The counted actions of each kind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CounterSnapshot {
+    pub key_presses: u64,
+    pub button_clicks: u64,
+    pub scroll_steps: u64,
+    pub touch_taps: u64,
+}
+
+impl Sub for CounterSnapshot {
+    type Output = CounterSnapshot;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        CounterSnapshot {
+            key_presses: self.key_presses.saturating_sub(rhs.key_presses),
+            button_clicks: self.button_clicks.saturating_sub(rhs.button_clicks),
+            scroll_steps: self.scroll_steps.saturating_sub(rhs.scroll_steps),
+            touch_taps: self.touch_taps.saturating_sub(rhs.touch_taps),
+        }
+    }
+}
+
+impl AddAssign for CounterSnapshot {
+    fn add_assign(&mut self, rhs: Self) {
+        self.key_presses += rhs.key_presses;
+        self.button_clicks += rhs.button_clicks;
+        self.scroll_steps += rhs.scroll_steps;
+        self.touch_taps += rhs.touch_taps;
+    }
+}
+
+/// The state of one seat. Each pressed key or button maps to the devices
+/// holding it down.
+#[derive(Debug, Default)]
+struct SeatState {
+    name: Option<String>,
+    counters: CounterSnapshot,
+    pressed_keys: HashMap<u32, HashSet<u64>>,
+    pressed_buttons: HashMap<u32, HashSet<u64>>,
+    last_scroll: Option<Instant>,
+}
+
+/// Marks `code` as held by `device`. Returns whether it was up on every
+/// device of the seat.
+fn press(held: &mut HashMap<u32, HashSet<u64>>, code: u32, device: u64) -> bool {
+    let devices = held.entry(code).or_default();
+    let was_up = devices.is_empty();
+    devices.insert(device);
+    was_up
+}
+
+fn release(held: &mut HashMap<u32, HashSet<u64>>, code: u32, device: u64) {
+    if let Some(devices) = held.get_mut(&code) {
+        devices.remove(&device);
+        if devices.is_empty() {
+            held.remove(&code);
+        }
+    }
+}
+
+/// One counting session, fed with events as they happen.
+///
+/// Press state is tracked per seat, and even while paused, so a key held
+/// across a pause is not counted when it repeats afterwards.
+#[derive(Debug)]
+pub struct Session {
+    /// The program being measured and its arguments, for the summary.
+    pub program: Vec<String>,
+    pub started_at: SystemTime,
+    pub scroll_debounce: Duration,
+    /// How much each kind of action counts towards the total.
+    pub weights: Weights,
+    /// Whether key repeats count as key presses.
+    pub count_repeats: bool,
+    counters: CounterSnapshot,
+    key_repeats: u64,
+    repeats: RepeatTracker,
+    clock: SessionClock,
+    laps: LapRecorder,
+    klm: KlmTracker,
+    modalities: ModalityTracker,
+    /// Presses per key code, kept in memory only and labelled according to
+    /// the privacy level on output.
+    key_presses: BTreeMap<u32, u64>,
+    labeler: KeyLabeler,
+    /// Text inputs that currently take a password.
+    sensitive_inputs: HashSet<u64>,
+    seats: BTreeMap<u32, SeatState>,
+    detectors: Vec<Box<dyn Detector>>,
+    recorder: Option<Recorder>,
+}
+
+impl Session {
+    /// Starts a session at `start`. Laps without a name take the next one
+    /// from `lap_names`.
+    pub fn new(start: Instant, lap_names: Vec<String>) -> Self {
+        Self {
+            program: Vec::new(),
+            started_at: SystemTime::now(),
+            scroll_debounce: DEFAULT_SCROLL_DEBOUNCE,
+            weights: Weights::default(),
+            count_repeats: false,
+            counters: CounterSnapshot::default(),
+            key_repeats: 0,
+            repeats: RepeatTracker::default(),
+            clock: SessionClock::new(start),
+            laps: LapRecorder::new(lap_names),
+            klm: KlmTracker::default(),
+            modalities: ModalityTracker::default(),
+            key_presses: BTreeMap::new(),
+            labeler: KeyLabeler::new(Privacy::default()),
+            sensitive_inputs: HashSet::new(),
+            seats: BTreeMap::new(),
+            detectors: Vec::new(),
+            recorder: None,
+        }
+    }
+
+    /// Adds a detector that observes the events from now on.
+    pub fn add_detector(&mut self, detector: Box<dyn Detector>) {
+        self.detectors.push(detector);
+    }
+
+    /// Handles an event from the default seat and device at `now` and
+    /// returns what it was counted as, if anything.
+    pub fn handle(&mut self, event: Event, now: Instant) -> Option<Action> {
+        self.handle_from(Source::default(), event, now)
+    }
+
+    /// Records the calls that change the session from now on to `out`, for
+    /// [`recording::replay`](crate::recording::replay). `start` is the
+    /// instant the session was started at.
+    pub fn record_to(&mut self, out: Box<dyn Write + Send>, start: Instant) {
+        let mut recorder = Recorder::new(out, start);
+        let start_record = Record::Start {
+            program: self.program.clone(),
+            started_at: unix_secs(self.started_at),
+            scroll_debounce_ms: self.scroll_debounce.as_millis() as u64,
+            count_repeats: self.count_repeats,
+            weights: self.weights,
+            privacy: self.privacy(),
+        };
+        recorder.write(start_record, start);
+        self.recorder = Some(recorder);
+    }
+
+    /// Ends the recording at `now`, if there is one, and returns the first
+    /// error writing it.
+    pub fn finish_recording(&mut self, now: Instant) -> io::Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(self.weights, now),
+            None => Ok(()),
+        }
+    }
+
+    fn record(&mut self, record: Record, now: Instant) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.write(record, now);
+        }
+    }
+
+    fn record_untimed(&mut self, record: Record) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.write_untimed(record);
+        }
+    }
+
+    /// Records `key` as pressed on `source`, or as a placeholder if it goes
+    /// down while a password is typed.
+    fn record_key_code(&mut self, source: Source, key: u32, pressed: bool) -> u32 {
+        let Some(recorder) = &mut self.recorder else {
+            return key;
+        };
+        let held = self
+            .seats
+            .get(&source.seat)
+            .is_some_and(|seat| seat.pressed_keys.contains_key(&key));
+        let masked = pressed && !held && !self.sensitive_inputs.is_empty();
+        recorder.key(source.seat, key, masked)
+    }
+
+    /// Handles an event from `source` at `now`.
+    pub fn handle_from(&mut self, source: Source, event: Event, now: Instant) -> Option<Action> {
+        if self.recorder.is_some() {
+            let recorded = match event {
+                Event::KeyPressed { key } => Event::KeyPressed {
+                    key: self.record_key_code(source, key, true),
+                },
+                Event::KeyReleased { key } => Event::KeyReleased {
+                    key: self.record_key_code(source, key, false),
+                },
+                Event::KeyRepeated { key } => Event::KeyRepeated {
+                    key: self.record_key_code(source, key, false),
+                },
+                event => event,
+            };
+            self.record(
+                Record::Event {
+                    source,
+                    event: recorded,
+                },
+                now,
+            );
+        }
+        let action = self.count(source, event, now);
+        if let Event::KeyReleased { key } = event {
+            self.forget_key_code(source.seat, key);
+        }
+        // Releases still reach the detectors, which track held keys
+        if !self.is_paused() || matches!(event, Event::KeyReleased { .. }) {
+            let at = self.clock.active_time(now);
+            for detector in &mut self.detectors {
+                detector.observe(&event, action, at);
+            }
+        }
+        action
+    }
+
+    /// Drops the placeholder of `key` once it is up on every device.
+    fn forget_key_code(&mut self, seat: u32, key: u32) {
+        let held = self
+            .seats
+            .get(&seat)
+            .is_some_and(|seat| seat.pressed_keys.contains_key(&key));
+        if let Some(recorder) = &mut self.recorder
+            && !held
+        {
+            recorder.forget(seat, key);
+        }
+    }
+
+    fn count(&mut self, source: Source, event: Event, now: Instant) -> Option<Action> {
+        let paused = self.is_paused();
+        let seat = self.seats.entry(source.seat).or_default();
+        match event {
+            Event::KeyPressed { key } => {
+                self.repeats.press(source, key, now);
+                if !press(&mut seat.pressed_keys, key, source.device) || paused {
+                    return None;
+                }
+                seat.counters.key_presses += 1;
+                self.counters.key_presses += 1;
+                self.record_key(key, 1);
+                self.klm.key();
+                self.record_modality(Modality::Keyboard, now);
+                Some(Action::Key(key))
+            }
+            Event::KeyReleased { key } => {
+                release(&mut seat.pressed_keys, key, source.device);
+                let repeats = self.repeats.release(source, key, now);
+                self.count_repeats(source, key, repeats)
+            }
+            Event::KeyRepeated { key } => {
+                if !self.repeats.repeated(source, key) {
+                    return None;
+                }
+                self.count_repeats(source, key, 1)
+            }
+            Event::ButtonPressed { button } => {
+                if !press(&mut seat.pressed_buttons, button, source.device) || paused {
+                    return None;
+                }
+                seat.counters.button_clicks += 1;
+                self.counters.button_clicks += 1;
+                self.klm.click();
+                self.record_modality(Modality::Pointer, now);
+                Some(Action::Click(button))
+            }
+            Event::ButtonReleased { button } => {
+                release(&mut seat.pressed_buttons, button, source.device);
+                None
+            }
+            Event::PointerEnter { x, y } => {
+                if !paused {
+                    self.klm.enter(x, y);
+                }
+                None
+            }
+            Event::PointerMotion { x, y } => {
+                if !paused {
+                    self.klm.motion(x, y);
+                }
+                None
+            }
+            Event::Scroll => {
+                let debounced = seat
+                    .last_scroll
+                    .is_some_and(|last| now.saturating_duration_since(last) < self.scroll_debounce);
+                if debounced || paused {
+                    return None;
+                }
+                seat.last_scroll = Some(now);
+                seat.counters.scroll_steps += 1;
+                self.counters.scroll_steps += 1;
+                self.klm.scroll();
+                self.record_modality(Modality::Pointer, now);
+                Some(Action::Scroll)
+            }
+            Event::TouchDown => {
+                if paused {
+                    return None;
+                }
+                seat.counters.touch_taps += 1;
+                self.counters.touch_taps += 1;
+                self.record_modality(Modality::Touch, now);
+                Some(Action::Touch)
+            }
+        }
+    }
+
+    fn count_repeats(&mut self, source: Source, key: u32, repeats: u64) -> Option<Action> {
+        if repeats == 0 || self.is_paused() {
+            return None;
+        }
+        self.key_repeats += repeats;
+        if self.count_repeats {
+            self.counters.key_presses += repeats;
+            self.record_key(key, repeats);
+            if let Some(seat) = self.seats.get_mut(&source.seat) {
+                seat.counters.key_presses += repeats;
+            }
+        }
+        Some(Action::Repeat(key, repeats))
+    }
+
+    /// Notes presses of `key` for the per-key detail, unless a password is
+    /// being typed.
+    fn record_key(&mut self, key: u32, presses: u64) {
+        if self.sensitive_inputs.is_empty() {
+            *self.key_presses.entry(key).or_default() += presses;
+        }
+    }
+
+    fn record_modality(&mut self, modality: Modality, now: Instant) {
+        let at = self.clock.active_time(now);
+        self.modalities.record(modality, at);
+    }
+
+    /// Sets the repeat rate and delay of a keyboard, as announced by
+    /// `wl_keyboard.repeat_info`, to estimate the repeats its client
+    /// generates.
+    pub fn set_repeat_info(&mut self, source: Source, info: RepeatInfo) {
+        let record = Record::RepeatInfo {
+            source,
+            rate: info.rate,
+            delay_ms: info.delay.as_millis() as u64,
+        };
+        self.record_untimed(record);
+        self.repeats.set_info(source, info);
+    }
+
+    /// Records a press that the input filter rule `rule` withheld or
+    /// remapped, which the session does not see otherwise, so a replay
+    /// reports the filtered presses too.
+    pub fn record_filtered(&mut self, rule: String, now: Instant) {
+        self.record(Record::Filtered { rule }, now);
+    }
+
+    /// Changes how scroll steps and key repeats are counted from now on, as
+    /// when a profile is selected by app_id.
+    pub fn set_counting(&mut self, scroll_debounce: Duration, count_repeats: bool) {
+        self.record_untimed(Record::Counting {
+            scroll_debounce_ms: scroll_debounce.as_millis() as u64,
+            count_repeats,
+        });
+        self.scroll_debounce = scroll_debounce;
+        self.count_repeats = count_repeats;
+    }
+
+    /// Sets how keys are labelled in the summary, drawing a new salt for
+    /// hashed labels.
+    pub fn set_privacy(&mut self, privacy: Privacy) {
+        self.labeler = KeyLabeler::new(privacy);
+    }
+
+    pub fn privacy(&self) -> Privacy {
+        self.labeler.privacy()
+    }
+
+    /// Marks a text input, numbered by the caller, as taking a password or
+    /// not. While any does, key presses still count but leave no per-key
+    /// detail.
+    pub fn set_sensitive(&mut self, input: u64, sensitive: bool) {
+        self.record_untimed(Record::Sensitive { input, sensitive });
+        if sensitive {
+            self.sensitive_inputs.insert(input);
+        } else {
+            self.sensitive_inputs.remove(&input);
+        }
+    }
+
+    /// Names a seat, as announced by `wl_seat.name`.
+    pub fn set_seat_name(&mut self, seat: u32, name: &str) {
+        self.record_untimed(Record::SeatName {
+            seat,
+            name: name.to_string(),
+        });
+        self.seats.entry(seat).or_default().name = Some(name.to_string());
+    }
+
+    /// Tracks a key press that is not an action, such as a hotkey. Returns
+    /// false if the key was already down on the seat.
+    pub fn hold_key(&mut self, source: Source, key: u32) -> bool {
+        if self.recorder.is_some() {
+            let key = self.record_key_code(source, key, true);
+            self.record_untimed(Record::Hold { source, key });
+        }
+        let seat = self.seats.entry(source.seat).or_default();
+        press(&mut seat.pressed_keys, key, source.device)
+    }
+
+    /// Sets the keys that `source` holds down, as listed by
+    /// `wl_keyboard.enter`. Keys it no longer holds are released; keys that
+    /// went down while another client had focus are held without counting.
+    /// Returns the released keys.
+    pub fn sync_keys(&mut self, source: Source, keys: &[u32], now: Instant) -> Vec<u32> {
+        let stale: Vec<u32> = self
+            .held_by(source, |seat| &seat.pressed_keys)
+            .filter(|key| !keys.contains(key))
+            .collect();
+        for &key in &stale {
+            self.handle_from(source, Event::KeyReleased { key }, now);
+        }
+        for &key in keys {
+            self.hold_key(source, key);
+        }
+        stale
+    }
+
+    /// Releases every key and button that `source` holds, e.g. when it
+    /// loses focus and will not see the releases, or is destroyed. Returns
+    /// the released keys.
+    pub fn release_device(&mut self, source: Source, now: Instant) -> Vec<u32> {
+        let keys: Vec<u32> = self.held_by(source, |seat| &seat.pressed_keys).collect();
+        let buttons: Vec<u32> = self.held_by(source, |seat| &seat.pressed_buttons).collect();
+        for &key in &keys {
+            self.handle_from(source, Event::KeyReleased { key }, now);
+        }
+        for button in buttons {
+            self.handle_from(source, Event::ButtonReleased { button }, now);
+        }
+        keys
+    }
+
+    /// The codes in one of the press maps of the seat that `source` holds.
+    fn held_by(
+        &self,
+        source: Source,
+        held: impl Fn(&SeatState) -> &HashMap<u32, HashSet<u64>>,
+    ) -> impl Iterator<Item = u32> {
+        self.seats
+            .get(&source.seat)
+            .into_iter()
+            .flat_map(move |seat| held(seat).iter())
+            .filter(move |(_, devices)| devices.contains(&source.device))
+            .map(|(&code, _)| code)
+    }
+
+    /// The keys that are currently down on `seat`.
+    pub fn pressed_keys(&self, seat: u32) -> HashSet<u32> {
+        self.seats
+            .get(&seat)
+            .map(|seat| seat.pressed_keys.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn snapshot(&self) -> CounterSnapshot {
+        self.counters
+    }
+
+    /// Repeats of held keys, whether or not they count as key presses.
+    pub fn key_repeats(&self) -> u64 {
+        self.key_repeats
+    }
+
+    /// Counted presses per evdev key code, regardless of the privacy level.
+    pub fn key_presses(&self) -> &BTreeMap<u32, u64> {
+        &self.key_presses
+    }
+
+    /// Counted presses per key label, as allowed by the privacy level.
+    pub fn key_detail(&self) -> BTreeMap<String, u64> {
+        let mut detail = BTreeMap::new();
+        for (&key, &presses) in &self.key_presses {
+            if let Some(label) = self.labeler.label(key) {
+                *detail.entry(label).or_default() += presses;
+            }
+        }
+        detail
+    }
+
+    pub fn klm(&self) -> KlmCounts {
+        self.klm.counts()
+    }
+
+    pub fn modalities(&self, now: Instant) -> ModalitySummary {
+        self.modalities.summary(self.clock.active_time(now))
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.clock.is_paused()
+    }
+
+    /// Returns false if counting was already paused.
+    pub fn pause(&mut self, now: Instant) -> bool {
+        let changed = self.clock.pause(now);
+        if changed {
+            self.record(Record::Pause, now);
+        }
+        changed
+    }
+
+    /// Returns false if counting was not paused.
+    pub fn resume(&mut self, now: Instant) -> bool {
+        let changed = self.clock.resume(now);
+        if changed {
+            self.record(Record::Resume, now);
+        }
+        changed
+    }
+
+    /// Time spent counting up to `now`.
+    pub fn active_time(&self, now: Instant) -> Duration {
+        self.clock.active_time(now)
+    }
+
+    pub fn paused_time(&self, now: Instant) -> Duration {
+        self.clock.paused_time(now)
+    }
+
+    /// Zeroes the counters and drops the laps, restarting the clock at `now`.
+    /// Keys and buttons that are down stay down.
+    pub fn reset(&mut self, now: Instant) {
+        self.record(Record::Reset, now);
+        self.counters = CounterSnapshot::default();
+        self.key_repeats = 0;
+        self.clock.reset(now);
+        self.laps.reset();
+        self.klm.reset();
+        self.modalities.reset();
+        self.key_presses.clear();
+        for seat in self.seats.values_mut() {
+            seat.counters = CounterSnapshot::default();
+        }
+        for detector in &mut self.detectors {
+            detector.reset();
+        }
+    }
+
+    /// Closes the current lap at `now` and starts a new one.
+    pub fn mark_lap(&mut self, name: Option<String>, now: Instant) -> Lap {
+        let at = self.clock.active_time(now);
+        let lap = self.laps.mark(name, at, self.counters);
+        let name = lap.name.clone();
+        self.record(Record::Lap { name }, now);
+        lap
+    }
+
+    /// The number of laps marked so far.
+    pub fn lap_count(&self) -> usize {
+        self.laps.len()
+    }
+
+    /// The marked laps plus the open one, or nothing if no lap was marked.
+    pub fn laps(&self, now: Instant) -> Vec<Lap> {
+        self.laps.finish(self.clock.active_time(now), self.counters)
+    }
+
+    /// The results of the session up to `now`.
+    pub fn summary(&self, now: Instant) -> Summary {
+        let duration = self.clock.active_time(now);
+        Summary {
+            program: self.program.clone(),
+            started_at: unix_secs(self.started_at),
+            ended_at: unix_secs(SystemTime::now()),
+            duration_secs: duration.as_secs_f64(),
+            paused_secs: self.clock.paused_time(now).as_secs_f64(),
+            counters: self.counters,
+            key_repeats: self.key_repeats,
+            repeats_counted: self.count_repeats,
+            privacy: self.privacy(),
+            keys: self.key_detail(),
+            filtered: BTreeMap::new(),
+            laps: self
+                .laps(now)
+                .into_iter()
+                .map(|lap| LapSummary {
+                    name: lap.name,
+                    duration_secs: lap.duration.as_secs_f64(),
+                    counters: lap.counts,
+                })
+                .collect(),
+            weights: self.weights,
+            klm: self.klm.counts(),
+            modalities: self.modalities.summary(duration),
+            metrics: self.metrics(now),
+            seats: self
+                .seats
+                .values()
+                .map(|seat| SeatSummary {
+                    name: seat.name.clone(),
+                    counters: seat.counters,
+                })
+                .collect(),
+        }
+    }
+
+    /// The metrics of all detectors, named `<detector>.<metric>`.
+    pub fn metrics(&self, now: Instant) -> BTreeMap<String, f64> {
+        let at = self.clock.active_time(now);
+        self.detectors
+            .iter()
+            .flat_map(|detector| {
+                detector
+                    .metrics(at)
+                    .into_iter()
+                    .map(|(name, value)| (format!("{}.{}", detector.name(), name), value))
+            })
+            .collect()
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::corrections::Corrections};
+
+    #[test]
+    fn test_repeated_presses_count_once() {
+        let now = Instant::now();
+        let mut session = Session::new(now, Vec::new());
+
+        // A second press without a release is a repeat or a duplicate
+        assert_eq!(
+            session.handle(Event::KeyPressed { key: 42 }, now),
+            Some(Action::Key(42))
+        );
+        assert_eq!(session.handle(Event::KeyPressed { key: 42 }, now), None);
+        session.handle(Event::KeyReleased { key: 42 }, now);
+        session.handle(Event::KeyPressed { key: 42 }, now);
+        assert_eq!(session.snapshot().key_presses, 2);
+        assert_eq!(session.key_presses()[&42], 2);
+
+        // BTN_LEFT
+        session.handle(Event::ButtonPressed { button: 272 }, now);
+        session.handle(Event::ButtonPressed { button: 272 }, now);
+        session.handle(Event::ButtonReleased { button: 272 }, now);
+        session.handle(Event::ButtonPressed { button: 272 }, now);
+        assert_eq!(session.snapshot().button_clicks, 2);
+        assert_eq!(session.weights.total(&session.snapshot()), 4.0);
+    }
+
+    #[test]
+    fn test_seats_and_devices() {
+        let now = Instant::now();
+        let mut session = Session::new(now, Vec::new());
+        let source = |seat, device| Source { seat, device };
+        session.set_seat_name(1, "stylus");
+
+        // Two keyboards of one seat get the same key events
+        session.handle_from(source(0, 0), Event::KeyPressed { key: 30 }, now);
+        session.handle_from(source(0, 1), Event::KeyPressed { key: 30 }, now);
+        // Another seat pressing the same key is another action
+        session.handle_from(source(1, 2), Event::KeyPressed { key: 30 }, now);
+        session.handle_from(source(0, 0), Event::KeyReleased { key: 30 }, now);
+        // Still held by the second keyboard
+        session.handle_from(source(0, 0), Event::KeyPressed { key: 30 }, now);
+        assert_eq!(session.pressed_keys(0), HashSet::from([30]));
+        session.handle_from(source(0, 0), Event::KeyReleased { key: 30 }, now);
+        session.handle_from(source(0, 1), Event::KeyReleased { key: 30 }, now);
+        assert!(session.pressed_keys(0).is_empty());
+        session.handle_from(source(0, 1), Event::KeyPressed { key: 30 }, now);
+
+        let summary = session.summary(now);
+        assert_eq!(summary.counters.key_presses, 3);
+        assert_eq!(summary.seats.len(), 2);
+        assert_eq!(summary.seats[0].name, None);
+        assert_eq!(summary.seats[0].counters.key_presses, 2);
+        assert_eq!(summary.seats[1].name.as_deref(), Some("stylus"));
+        assert_eq!(summary.seats[1].counters.key_presses, 1);
+    }
+
+    #[test]
+    fn test_focus_changes() {
+        let now = Instant::now();
+        let mut session = Session::new(now, Vec::new());
+        let keyboard = Source { seat: 0, device: 0 };
+        let pointer = Source { seat: 0, device: 1 };
+
+        // Released after `leave`, so the client never sees it
+        session.handle_from(keyboard, Event::KeyPressed { key: 30 }, now);
+        assert_eq!(session.release_device(keyboard, now), [30]);
+        assert_eq!(
+            session.handle_from(keyboard, Event::KeyPressed { key: 30 }, now),
+            Some(Action::Key(30))
+        );
+
+        // `enter` lists the held keys: 30 went up elsewhere, 31 went down
+        assert_eq!(session.sync_keys(keyboard, &[31], now), [30]);
+        assert_eq!(
+            session.handle_from(keyboard, Event::KeyPressed { key: 31 }, now),
+            None
+        );
+        session.handle_from(keyboard, Event::KeyReleased { key: 31 }, now);
+        session.handle_from(keyboard, Event::KeyPressed { key: 31 }, now);
+
+        // A pointer destroyed with a button down
+        session.handle_from(pointer, Event::ButtonPressed { button: 272 }, now);
+        assert!(session.release_device(pointer, now).is_empty());
+        session.handle_from(pointer, Event::ButtonPressed { button: 272 }, now);
+
+        assert_eq!(session.snapshot().key_presses, 3);
+        assert_eq!(session.snapshot().button_clicks, 2);
+    }
+
+    #[test]
+    fn test_key_repeats() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut session = Session::new(start, Vec::new());
+        session.count_repeats = true;
+        let keyboard = Source::default();
+        let info = RepeatInfo {
+            rate: 25,
+            delay: Duration::from_millis(600),
+        };
+        session.set_repeat_info(keyboard, info);
+
+        // Backspace held for 2.2s, repeated by the client
+        session.handle(Event::KeyPressed { key: 14 }, ms(0));
+        assert_eq!(
+            session.handle(Event::KeyReleased { key: 14 }, ms(2200)),
+            Some(Action::Repeat(14, 41))
+        );
+        // Repeated by the compositor
+        session.handle(Event::KeyPressed { key: 30 }, ms(3000));
+        session.handle(Event::KeyRepeated { key: 30 }, ms(3600));
+        session.handle(Event::KeyRepeated { key: 30 }, ms(3640));
+        session.handle(Event::KeyReleased { key: 30 }, ms(3700));
+
+        let summary = session.summary(ms(4000));
+        assert_eq!(summary.key_repeats, 43);
+        assert_eq!(summary.counters.key_presses, 45);
+        assert_eq!(session.key_presses()[&14], 42);
+    }
+
+    #[test]
+    fn test_key_privacy() {
+        let now = Instant::now();
+        let mut session = Session::new(now, Vec::new());
+        let tap = |session: &mut Session, key| {
+            session.handle(Event::KeyPressed { key }, now);
+            session.handle(Event::KeyReleased { key }, now);
+        };
+        tap(&mut session, 30);
+        // A password field has focus
+        session.set_sensitive(0, true);
+        tap(&mut session, 31);
+        tap(&mut session, 2);
+        session.set_sensitive(0, false);
+        tap(&mut session, 103);
+
+        assert_eq!(session.snapshot().key_presses, 4);
+        assert_eq!(
+            session.key_detail(),
+            BTreeMap::from([("A".to_string(), 1), ("Up".to_string(), 1)])
+        );
+        session.set_privacy(Privacy::Classes);
+        assert_eq!(
+            session.key_detail(),
+            BTreeMap::from([("letters".to_string(), 1), ("navigation".to_string(), 1)])
+        );
+        session.set_privacy(Privacy::Redact);
+        assert!(session.summary(now).keys.is_empty());
+    }
+
+    #[test]
+    fn test_scroll_debounce() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut session = Session::new(start, Vec::new());
+        assert_eq!(session.handle(Event::Scroll, ms(0)), Some(Action::Scroll));
+        assert_eq!(session.handle(Event::Scroll, ms(50)), None);
+        assert_eq!(session.handle(Event::Scroll, ms(120)), Some(Action::Scroll));
+        assert_eq!(session.snapshot().scroll_steps, 2);
+    }
+
+    #[test]
+    fn test_paused_session() {
+        let start = Instant::now();
+        let secs = |n| start + Duration::from_secs(n);
+        let mut session = Session::new(start, vec!["login".to_string()]);
+        session.add_detector(Box::new(Corrections::default()));
+        session.handle(Event::KeyPressed { key: 14 }, secs(1));
+        session.mark_lap(None, secs(2));
+
+        assert!(session.pause(secs(3)));
+        assert_eq!(session.handle(Event::KeyPressed { key: 31 }, secs(4)), None);
+        assert_eq!(session.handle(Event::TouchDown, secs(4)), None);
+        assert!(session.resume(secs(5)));
+        // Still down from before the resume
+        assert_eq!(session.handle(Event::KeyPressed { key: 31 }, secs(6)), None);
+        session.handle(Event::TouchDown, secs(6));
+
+        let summary = session.summary(secs(8));
+        assert_eq!(summary.duration_secs, 6.0);
+        assert_eq!(summary.paused_secs, 2.0);
+        assert_eq!(summary.counters.key_presses, 1);
+        assert_eq!(summary.laps[0].name.as_deref(), Some("login"));
+        assert_eq!(summary.laps[1].counters.touch_taps, 1);
+        assert_eq!(summary.modalities.switches, 1);
+        assert_eq!(summary.metrics["corrections.erases"], 1.0);
+
+        session.reset(secs(9));
+        assert_eq!(session.snapshot(), CounterSnapshot::default());
+        assert_eq!(session.lap_count(), 0);
+        assert_eq!(session.metrics(secs(9))["corrections.erases"], 0.0);
+    }
+
+    #[test]
+    fn test_released_while_paused() {
+        let start = Instant::now();
+        let secs = |n| start + Duration::from_secs(n);
+        let mut session = Session::new(start, Vec::new());
+        session.add_detector(Box::new(Corrections::default()));
+        // Control is let go while paused
+        session.handle(Event::KeyPressed { key: 29 }, secs(1));
+        session.pause(secs(2));
+        session.handle(Event::KeyReleased { key: 29 }, secs(3));
+        session.resume(secs(4));
+        session.handle(Event::KeyPressed { key: 44 }, secs(5));
+        // Control is pressed and let go while paused
+        session.pause(secs(6));
+        session.handle(Event::KeyPressed { key: 97 }, secs(7));
+        session.handle(Event::KeyReleased { key: 97 }, secs(7));
+        session.resume(secs(8));
+        session.handle(Event::KeyReleased { key: 44 }, secs(9));
+        session.handle(Event::KeyPressed { key: 44 }, secs(9));
+
+        assert_eq!(session.metrics(secs(10))["corrections.undos"], 0.0);
+    }
+}
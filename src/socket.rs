@@ -0,0 +1,277 @@
+//! Control socket speaking line-delimited JSON.
+//!
+//! Each request is a single line, either a JSON object such as
+//! `{"command": "lap", "name": "login"}` or the plain form `lap login`.
+//! Every request is answered with a single JSON line that has an `ok` field.
+
+use {
+    crate::{actions, controls::Controls, remote::Remote},
+    serde::Deserialize,
+    serde_json::{Value, json},
+    std::{
+        env, fs,
+        io::{self, BufRead, BufReader, Write},
+        os::unix::{
+            fs::FileTypeExt,
+            net::{UnixListener, UnixStream},
+        },
+        path::{Path, PathBuf},
+        process, thread,
+        time::Instant,
+    },
+    wl_actions::{laps::Lap, session::CounterSnapshot, weights::Weights},
+};
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum Request {
+    Status,
+    Snapshot,
+    Reset,
+    Lap {
+        #[serde(default)]
+        name: Option<String>,
+    },
+    Pause,
+    Resume,
+    Stop,
+}
+
+/// Returns `$XDG_RUNTIME_DIR/wl-actions-<pid>.sock`, or `None` without a
+/// runtime directory: other users may connect to a socket in a shared one
+/// such as `/tmp` before its permissions are set.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_RUNTIME_DIR")?;
+    Some(PathBuf::from(dir).join(format!("wl-actions-{}.sock", process::id())))
+}
+
+/// Binds the socket, which only the user may connect to. Called before any
+/// other thread starts, as it changes the umask of the process meanwhile.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    // Remove a stale socket left behind by a crashed session, but nothing else
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    // Created as 0600 rather than restricted after binding, so nobody else
+    // can connect in between
+    // SAFETY: umask only changes the mask of the process
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    // SAFETY: as above
+    unsafe { libc::umask(umask) };
+    listener
+}
+
+/// Accepts connections on a background thread, one thread per client.
+pub fn spawn(listener: UnixListener, remote: Remote) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let remote = remote.clone();
+            thread::spawn(move || {
+                let _ = serve(stream, &remote);
+            });
+        }
+    });
+}
+
+/// Reads requests and has the proxy thread run them.
+fn serve(stream: UnixStream, remote: &Remote) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (response, stop) = match parse_request(&line) {
+            Ok(request) => {
+                let stop = request == Request::Stop;
+                let response = remote
+                    .call(move |controls| handle(&request, controls))
+                    .unwrap_or_else(|| json!({ "ok": false, "error": "the session has ended" }));
+                (response, stop)
+            }
+            Err(err) => (json!({ "ok": false, "error": err }), false),
+        };
+        writeln!(writer, "{}", response)?;
+        if stop {
+            remote.call(|controls| {
+                actions::stop(controls);
+            });
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_request(line: &str) -> Result<Request, String> {
+    let line = line.trim();
+    if line.starts_with('{') {
+        return serde_json::from_str(line).map_err(|err| err.to_string());
+    }
+    let (command, arg) = match line.split_once(char::is_whitespace) {
+        Some((command, arg)) => (command, Some(arg.trim().to_string())),
+        None => (line, None),
+    };
+    let request = match command {
+        "status" => Request::Status,
+        "snapshot" => Request::Snapshot,
+        "reset" => Request::Reset,
+        "lap" => Request::Lap { name: arg },
+        "pause" => Request::Pause,
+        "resume" => Request::Resume,
+        "stop" => Request::Stop,
+        _ => return Err(format!("unknown command `{}`", command)),
+    };
+    Ok(request)
+}
+
+/// Runs a request and returns the response. `stop` only acknowledges the
+/// request; the caller ends the session after sending the response.
+pub fn handle(request: &Request, controls: &Controls) -> Value {
+    match request {
+        Request::Status => {
+            let now = Instant::now();
+            let session = controls.session.borrow();
+            json!({
+                "ok": true,
+                "paused": session.is_paused(),
+                "duration_secs": session.active_time(now).as_secs_f64(),
+                "paused_secs": session.paused_time(now).as_secs_f64(),
+                "laps": session.lap_count(),
+            })
+        }
+        Request::Snapshot => {
+            let now = Instant::now();
+            let session = controls.session.borrow();
+            json!({
+                "ok": true,
+                "duration_secs": session.active_time(now).as_secs_f64(),
+                "counters": counters_json(&session.snapshot(), &session.weights),
+                "laps": session
+                    .laps(now)
+                    .iter()
+                    .map(|lap| lap_json(lap, &session.weights))
+                    .collect::<Vec<_>>(),
+            })
+        }
+        Request::Reset => {
+            controls.reset();
+            json!({ "ok": true })
+        }
+        Request::Lap { name } => {
+            let lap = controls.record_lap(name.clone());
+            json!({ "ok": true, "lap": lap_json(&lap, &controls.weights()) })
+        }
+        Request::Pause => json!({ "ok": true, "changed": controls.pause() }),
+        Request::Resume => json!({ "ok": true, "changed": controls.resume() }),
+        Request::Stop => json!({ "ok": true }),
+    }
+}
+
+fn counters_json(snapshot: &CounterSnapshot, weights: &Weights) -> Value {
+    json!({
+        "key_presses": snapshot.key_presses,
+        "button_clicks": snapshot.button_clicks,
+        "scroll_steps": snapshot.scroll_steps,
+        "touch_taps": snapshot.touch_taps,
+        "total": weights.total(snapshot),
+    })
+}
+
+fn lap_json(lap: &Lap, weights: &Weights) -> Value {
+    json!({
+        "name": lap.name,
+        "duration_secs": lap.duration.as_secs_f64(),
+        "counters": counters_json(&lap.counts, weights),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::os::unix::fs::PermissionsExt,
+        wl_actions::{Event, Session},
+    };
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(parse_request("status"), Ok(Request::Status));
+        assert_eq!(
+            parse_request("lap  login page "),
+            Ok(Request::Lap {
+                name: Some("login page".to_string())
+            })
+        );
+        assert_eq!(parse_request("lap"), Ok(Request::Lap { name: None }));
+        assert_eq!(
+            parse_request(r#"{"command": "lap", "name": "search"}"#),
+            Ok(Request::Lap {
+                name: Some("search".to_string())
+            })
+        );
+        assert_eq!(parse_request(r#"{"command": "pause"}"#), Ok(Request::Pause));
+        assert!(parse_request("jump").is_err());
+        assert!(parse_request(r#"{"command": "jump"}"#).is_err());
+    }
+
+    #[test]
+    fn test_handle_requests() {
+        let now = Instant::now();
+        let controls = Controls::new(Session::new(now, Vec::new()), false);
+        let press = |key| {
+            let mut session = controls.session.borrow_mut();
+            session.handle(Event::KeyPressed { key }, now);
+            session.handle(Event::KeyReleased { key }, now);
+        };
+        (30..33).for_each(press);
+
+        let response = handle(&Request::Lap { name: None }, &controls);
+        assert_eq!(response["lap"]["counters"]["key_presses"], 3);
+
+        let response = handle(&Request::Pause, &controls);
+        assert_eq!(response["changed"], true);
+        let response = handle(&Request::Status, &controls);
+        assert_eq!(response["paused"], true);
+        assert_eq!(response["laps"], 1);
+
+        let response = handle(&Request::Resume, &controls);
+        assert_eq!(response["changed"], true);
+        for button in [272, 273] {
+            let event = Event::ButtonPressed { button };
+            controls.session.borrow_mut().handle(event, Instant::now());
+        }
+        let response = handle(&Request::Snapshot, &controls);
+        assert_eq!(response["counters"]["total"], 5.0);
+        assert_eq!(response["laps"][1]["counters"]["button_clicks"], 2);
+
+        handle(&Request::Reset, &controls);
+        let response = handle(&Request::Snapshot, &controls);
+        assert_eq!(response["counters"]["total"], 0.0);
+        assert_eq!(response["laps"].as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_bind_refuses_other_files() {
+        let path = env::temp_dir().join(format!("wl-actions-test-{}.sock", process::id()));
+        fs::write(&path, "keep").unwrap();
+        let err = bind(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "keep");
+
+        fs::remove_file(&path).unwrap();
+        drop(bind(&path).unwrap());
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // The stale socket is replaced
+        drop(bind(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+//! Continuous status bar output for waybar and i3bar.
+
+use {
+    crate::controls::{Activity, IDLE_AFTER, SessionSnapshot},
+    clap::ValueEnum,
+    serde::Deserialize,
+    serde_json::json,
+    std::{
+        fs::OpenOptions,
+        io::{self, Write},
+        os::unix::fs::OpenOptionsExt,
+        path::PathBuf,
+        time::{Duration, Instant},
+    },
+    wl_actions::{session::CounterSnapshot, weights::format_total},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusBarFormat {
+    /// One JSON object per line, for a waybar custom module with `return-type: json`.
+    Waybar,
+    /// The i3bar protocol, for i3bar and swaybar.
+    I3bar,
+}
+
+/// What the bar shows.
+#[derive(Clone, Copy, Debug)]
+pub struct BarState {
+    pub counts: CounterSnapshot,
+    /// The weighted total of `counts`.
+    pub total: f64,
+    pub duration: Duration,
+    pub laps: usize,
+    pub paused: bool,
+    pub idle: bool,
+}
+
+impl BarState {
+    fn class(&self) -> &'static str {
+        if self.paused {
+            "paused"
+        } else if self.idle {
+            "idle"
+        } else {
+            "active"
+        }
+    }
+
+    fn text(&self) -> String {
+        let prefix = if self.paused { "PAUSED " } else { "" };
+        format!(
+            "{}K {} C {} S {} T {} = {}",
+            prefix,
+            self.counts.key_presses,
+            self.counts.button_clicks,
+            self.counts.scroll_steps,
+            self.counts.touch_taps,
+            format_total(self.total)
+        )
+    }
+
+    fn tooltip(&self) -> String {
+        let minutes = self.duration.as_secs_f64() / 60.0;
+        let apm = if minutes > 0.0 {
+            self.total / minutes
+        } else {
+            0.0
+        };
+        format!(
+            "Duration: {}s\nKey presses: {}\nButton clicks: {}\nScroll steps: {}\nTouch taps: {}\nTotal actions: {}\nActions per minute: {:.1}\nLaps: {}",
+            self.duration.as_secs(),
+            self.counts.key_presses,
+            self.counts.button_clicks,
+            self.counts.scroll_steps,
+            self.counts.touch_taps,
+            format_total(self.total),
+            apm,
+            self.laps
+        )
+    }
+}
+
+/// Renders one update. `first` is false for every line after the first one
+/// written to the same output, which i3bar needs to separate with a comma.
+pub fn render(format: StatusBarFormat, state: &BarState, first: bool) -> String {
+    match format {
+        StatusBarFormat::Waybar => json!({
+            "text": state.text(),
+            "tooltip": state.tooltip(),
+            "class": state.class(),
+            "alt": state.class(),
+        })
+        .to_string(),
+        StatusBarFormat::I3bar => {
+            let block = json!([{
+                "name": "wl-actions",
+                "instance": state.class(),
+                "full_text": state.text(),
+                "short_text": format_total(state.total),
+            }]);
+            if first {
+                block.to_string()
+            } else {
+                format!(",{}", block)
+            }
+        }
+    }
+}
+
+fn header(format: StatusBarFormat) -> Option<&'static str> {
+    match format {
+        StatusBarFormat::Waybar => None,
+        StatusBarFormat::I3bar => Some("{\"version\":1}\n[\n"),
+    }
+}
+
+/// Writes the bar updates, either to stdout or to a path such as a FIFO.
+pub struct StatusBar {
+    format: StatusBarFormat,
+    path: Option<PathBuf>,
+    writer: Option<Box<dyn Write + Send>>,
+    lines_written: u64,
+    last_written: Option<(&'static str, String, u64)>,
+    activity: Activity,
+}
+
+impl StatusBar {
+    pub fn new(format: StatusBarFormat, path: Option<PathBuf>) -> Self {
+        Self {
+            format,
+            path,
+            writer: None,
+            lines_written: 0,
+            last_written: None,
+            activity: Activity::new(Instant::now()),
+        }
+    }
+
+    /// Called by the display thread on every tick; only writes on changes.
+    pub fn update(&mut self, snapshot: &SessionSnapshot) {
+        let now = Instant::now();
+        let counts = snapshot.counts;
+        let duration = snapshot.active_time(now);
+        let state = BarState {
+            counts,
+            total: snapshot.weights.total(&counts),
+            duration,
+            laps: snapshot.laps,
+            paused: snapshot.paused,
+            idle: self.activity.idle_time(&counts, now) >= IDLE_AFTER,
+        };
+        // The duration in the tooltip only changes every second, which is
+        // often enough for the bar
+        let written = (state.class(), state.text(), duration.as_secs());
+        if self.last_written.as_ref() == Some(&written) {
+            return;
+        }
+        if self.write(&state).is_ok() {
+            self.last_written = Some(written);
+        } else {
+            // The reader went away; reopen on the next tick
+            self.writer = None;
+        }
+    }
+
+    fn write(&mut self, state: &BarState) -> io::Result<()> {
+        if self.writer.is_none() {
+            let mut writer = self.open()?;
+            if let Some(header) = header(self.format) {
+                writer.write_all(header.as_bytes())?;
+            }
+            self.writer = Some(writer);
+            self.lines_written = 0;
+        }
+        let line = render(self.format, state, self.lines_written == 0);
+        let writer = self.writer.as_mut().unwrap();
+        writeln!(writer, "{}", line)?;
+        writer.flush()?;
+        self.lines_written += 1;
+        Ok(())
+    }
+
+    fn open(&self) -> io::Result<Box<dyn Write + Send>> {
+        match &self.path {
+            None => Ok(Box::new(io::stdout())),
+            // Non-blocking, so a FIFO without a reader doesn't stall the
+            // display thread
+            Some(path) => Ok(Box::new(
+                OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .custom_flags(libc::O_NONBLOCK)
+                    .open(path)?,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::Value};
+
+    fn state() -> BarState {
+        BarState {
+            counts: CounterSnapshot {
+                key_presses: 42,
+                button_clicks: 15,
+                scroll_steps: 8,
+                touch_taps: 3,
+            },
+            total: 60.0,
+            duration: Duration::from_secs(120),
+            laps: 2,
+            paused: false,
+            idle: false,
+        }
+    }
+
+    #[test]
+    fn test_render_waybar() {
+        let line = render(StatusBarFormat::Waybar, &state(), true);
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["text"], "K 42 C 15 S 8 T 3 = 60");
+        assert_eq!(value["class"], "active");
+        assert!(
+            value["tooltip"]
+                .as_str()
+                .unwrap()
+                .contains("Actions per minute: 30.0")
+        );
+
+        let paused = BarState {
+            paused: true,
+            idle: true,
+            ..state()
+        };
+        let value: Value =
+            serde_json::from_str(&render(StatusBarFormat::Waybar, &paused, false)).unwrap();
+        assert_eq!(value["class"], "paused");
+        assert!(value["text"].as_str().unwrap().starts_with("PAUSED"));
+    }
+
+    #[test]
+    fn test_render_i3bar() {
+        let idle = BarState {
+            idle: true,
+            ..state()
+        };
+        let first = render(StatusBarFormat::I3bar, &idle, true);
+        let value: Value = serde_json::from_str(&first).unwrap();
+        assert_eq!(value[0]["full_text"], "K 42 C 15 S 8 T 3 = 60");
+        assert_eq!(value[0]["instance"], "idle");
+
+        let next = render(StatusBarFormat::I3bar, &idle, false);
+        assert_eq!(next, format!(",{}", first));
+    }
+}
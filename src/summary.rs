@@ -0,0 +1,227 @@
+use {
+    crate::{
+        klm::KlmCounts,
+        laps::lap_label,
+        modality::ModalitySummary,
+        privacy::Privacy,
+        session::CounterSnapshot,
+        weights::{Weights, format_total},
+    },
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        io::{self, Write},
+        time::Duration,
+    },
+};
+
+/// The results of a finished session.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    /// The wrapped program and its arguments.
+    pub program: Vec<String>,
+    /// Unix timestamps in seconds.
+    pub started_at: u64,
+    pub ended_at: u64,
+    /// Time spent counting, excluding pauses.
+    pub duration_secs: f64,
+    pub paused_secs: f64,
+    pub counters: CounterSnapshot,
+    /// Repeats of held keys, sent by the compositor or estimated.
+    #[serde(default)]
+    pub key_repeats: u64,
+    /// Whether `key_repeats` are included in the key presses.
+    #[serde(default)]
+    pub repeats_counted: bool,
+    /// How the keys in `keys` are labelled.
+    #[serde(default)]
+    pub privacy: Privacy,
+    /// Key presses per key, by name, hash or class depending on `privacy`.
+    #[serde(default)]
+    pub keys: BTreeMap<String, u64>,
+    /// Presses dropped or remapped by each input filter rule.
+    #[serde(default)]
+    pub filtered: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub laps: Vec<LapSummary>,
+    /// The weights the totals are computed with.
+    #[serde(default)]
+    pub weights: Weights,
+    /// Keystroke-Level Model operators.
+    #[serde(default)]
+    pub klm: KlmCounts,
+    /// Switches between the keyboard, the pointer and touch.
+    #[serde(default)]
+    pub modalities: ModalitySummary,
+    /// Metrics of the detectors, named `<detector>.<metric>`.
+    #[serde(default)]
+    pub metrics: BTreeMap<String, f64>,
+    /// The counters of each seat, in the order the seats were bound.
+    #[serde(default)]
+    pub seats: Vec<SeatSummary>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LapSummary {
+    pub name: Option<String>,
+    pub duration_secs: f64,
+    pub counters: CounterSnapshot,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SeatSummary {
+    /// The name from `wl_seat.name`, if the compositor sent one.
+    pub name: Option<String>,
+    pub counters: CounterSnapshot,
+}
+
+impl Summary {
+    pub fn total_actions(&self) -> f64 {
+        self.weights.total(&self.counters)
+    }
+
+    pub fn actions_per_minute(&self) -> f64 {
+        if self.duration_secs > 0.0 {
+            (self.total_actions() / self.duration_secs) * 60.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Writes the human-readable summary shown at the end of a session.
+pub fn write(out: &mut impl Write, summary: &Summary) -> io::Result<()> {
+    let duration = Duration::from_secs_f64(summary.duration_secs);
+    let paused = Duration::from_secs_f64(summary.paused_secs);
+    let keys = summary.counters.key_presses;
+    let clicks = summary.counters.button_clicks;
+    let scrolls = summary.counters.scroll_steps;
+    let touch = summary.counters.touch_taps;
+    let total = summary.total_actions();
+    let apm = summary.actions_per_minute();
+
+    writeln!(out, "\n=== Action Summary ===")?;
+    writeln!(out, "Duration: {}", format_duration(duration))?;
+    if !paused.is_zero() {
+        writeln!(out, "Paused: {} (excluded)", format_duration(paused))?;
+    }
+    writeln!(out, "Key presses: {}", keys)?;
+    if summary.key_repeats > 0 {
+        let counted = if summary.repeats_counted {
+            "included in key presses"
+        } else {
+            "not counted"
+        };
+        writeln!(out, "Key repeats: {} ({})", summary.key_repeats, counted)?;
+    }
+    writeln!(out, "Button clicks: {}", clicks)?;
+    if summary.weights.scrolls == 0.0 {
+        writeln!(out, "Scroll steps: {} (tracked separately)", scrolls)?;
+    } else {
+        writeln!(out, "Scroll steps: {}", scrolls)?;
+    }
+    writeln!(out, "Touch taps: {}", touch)?;
+    writeln!(
+        out,
+        "Total actions: {} ({})",
+        format_total(total),
+        summary.weights
+    )?;
+    writeln!(out, "Actions per minute: {:.1}", apm)?;
+
+    if !summary.klm.is_empty() {
+        let klm = &summary.klm;
+        writeln!(out, "\n=== KLM Estimate ===")?;
+        writeln!(
+            out,
+            "Operators: K {} | P {} | B {} | H {}",
+            klm.keystrokes, klm.points, klm.buttons, klm.homings
+        )?;
+        writeln!(
+            out,
+            "Predicted expert time: {:.1}s (measured {})",
+            klm.predicted_time().as_secs_f64(),
+            format_duration(duration)
+        )?;
+    }
+
+    if !summary.modalities.is_empty() {
+        let modalities = &summary.modalities;
+        writeln!(out, "\n=== Modalities ===")?;
+        writeln!(out, "Switches: {}", modalities.switches)?;
+        writeln!(
+            out,
+            "Keyboard: {} | Pointer: {} | Touch: {}",
+            format_duration(Duration::from_secs_f64(modalities.keyboard_secs)),
+            format_duration(Duration::from_secs_f64(modalities.pointer_secs)),
+            format_duration(Duration::from_secs_f64(modalities.touch_secs))
+        )?;
+    }
+
+    if !summary.metrics.is_empty() {
+        writeln!(out, "\n=== Metrics ===")?;
+        for (name, value) in &summary.metrics {
+            if value.fract() == 0.0 {
+                writeln!(out, "{}: {}", name, value)?;
+            } else {
+                writeln!(out, "{}: {:.2}", name, value)?;
+            }
+        }
+    }
+
+    if !summary.filtered.is_empty() {
+        writeln!(out, "\n=== Filtered ===")?;
+        for (rule, count) in &summary.filtered {
+            writeln!(out, "{}: {}", rule, count)?;
+        }
+    }
+
+    if summary.seats.len() > 1 {
+        writeln!(out, "\n=== Seats ===")?;
+        for (i, seat) in summary.seats.iter().enumerate() {
+            writeln!(
+                out,
+                "{}: Keys: {} | Clicks: {} | Scrolls: {} | Touch: {} | Total: {}",
+                seat.name
+                    .clone()
+                    .unwrap_or_else(|| format!("Seat {}", i + 1)),
+                seat.counters.key_presses,
+                seat.counters.button_clicks,
+                seat.counters.scroll_steps,
+                seat.counters.touch_taps,
+                format_total(summary.weights.total(&seat.counters))
+            )?;
+        }
+    }
+
+    if !summary.laps.is_empty() {
+        writeln!(out, "\n=== Laps ===")?;
+        for (i, lap) in summary.laps.iter().enumerate() {
+            writeln!(
+                out,
+                "Lap {}{}: {} | Keys: {} | Clicks: {} | Scrolls: {} | Touch: {} | Total: {}",
+                i + 1,
+                lap_label(lap.name.as_deref()),
+                format_duration(Duration::from_secs_f64(lap.duration_secs)),
+                lap.counters.key_presses,
+                lap.counters.button_clicks,
+                lap.counters.scroll_steps,
+                lap.counters.touch_taps,
+                format_total(summary.weights.total(&lap.counters))
+            )?;
+        }
+    }
+    Ok(())
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let mins = secs / 60;
+    let secs_remainder = secs % 60;
+
+    if mins > 0 {
+        format!("{}m {}s", mins, secs_remainder)
+    } else {
+        format!("{}s", secs)
+    }
+}
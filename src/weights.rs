@@ -0,0 +1,166 @@
+//! How much each kind of action counts towards the total.
+
+use {
+    crate::session::CounterSnapshot,
+    serde::{Deserialize, Serialize},
+    std::{fmt, str::FromStr},
+    thiserror::Error,
+};
+
+#[derive(Debug, Error)]
+pub enum WeightsError {
+    #[error("`{0}` is not of the form `<kind>=<weight>`, e.g. `scrolls=0.5`")]
+    Syntax(String),
+    #[error("unknown action kind `{0}`, expected keys, clicks, scrolls or touch")]
+    UnknownKind(String),
+    #[error("`{0}` is not a valid weight")]
+    InvalidWeight(String),
+}
+
+/// The weight of each action kind in the total. By default scroll steps are
+/// left out, as they are too granular to compare with key presses. Chords
+/// have no weight of their own: a chord is already counted as the key
+/// presses it is made of.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Weights {
+    pub keys: f64,
+    pub clicks: f64,
+    pub scrolls: f64,
+    pub touch: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            keys: 1.0,
+            clicks: 1.0,
+            scrolls: 0.0,
+            touch: 1.0,
+        }
+    }
+}
+
+impl Weights {
+    pub fn total(&self, counts: &CounterSnapshot) -> f64 {
+        self.keys * counts.key_presses as f64
+            + self.clicks * counts.button_clicks as f64
+            + self.scrolls * counts.scroll_steps as f64
+            + self.touch * counts.touch_taps as f64
+    }
+
+    fn terms(&self) -> [(&'static str, f64); 4] {
+        [
+            ("keys", self.keys),
+            ("clicks", self.clicks),
+            ("scrolls", self.scrolls),
+            ("touch", self.touch),
+        ]
+    }
+}
+
+/// Parses weights such as `scrolls=0.5,touch=2`; kinds that are not given
+/// keep their default weight.
+impl FromStr for Weights {
+    type Err = WeightsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = Self::default();
+        for term in s.split(',').filter(|term| !term.trim().is_empty()) {
+            let (kind, weight) = term
+                .split_once('=')
+                .ok_or_else(|| WeightsError::Syntax(term.trim().to_string()))?;
+            let weight = weight
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|weight| weight.is_finite() && *weight >= 0.0)
+                .ok_or_else(|| WeightsError::InvalidWeight(weight.trim().to_string()))?;
+            let field = match kind.trim() {
+                "keys" => &mut weights.keys,
+                "clicks" => &mut weights.clicks,
+                "scrolls" => &mut weights.scrolls,
+                "touch" => &mut weights.touch,
+                kind => return Err(WeightsError::UnknownKind(kind.to_string())),
+            };
+            *field = weight;
+        }
+        Ok(weights)
+    }
+}
+
+/// The formula, e.g. `keys + clicks + 0.5×scrolls + touch`.
+impl fmt::Display for Weights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let terms: Vec<_> = self
+            .terms()
+            .into_iter()
+            .filter(|&(_, weight)| weight != 0.0)
+            .map(|(kind, weight)| {
+                if weight == 1.0 {
+                    kind.to_string()
+                } else {
+                    format!("{}×{}", weight, kind)
+                }
+            })
+            .collect();
+        if terms.is_empty() {
+            write!(f, "nothing")
+        } else {
+            write!(f, "{}", terms.join(" + "))
+        }
+    }
+}
+
+/// Formats a total without a fraction when the weights keep it whole.
+pub fn format_total(total: f64) -> String {
+    if total.fract() == 0.0 {
+        format!("{}", total)
+    } else {
+        format!("{:.1}", total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weights() {
+        let counts = CounterSnapshot {
+            key_presses: 42,
+            button_clicks: 15,
+            scroll_steps: 8,
+            touch_taps: 3,
+        };
+        let weights = Weights::default();
+        assert_eq!(weights.total(&counts), 60.0);
+        assert_eq!(weights.to_string(), "keys + clicks + touch");
+
+        let weights: Weights = "scrolls=0.5, touch=0".parse().unwrap();
+        assert_eq!(weights.total(&counts), 61.0);
+        assert_eq!(weights.to_string(), "keys + clicks + 0.5×scrolls");
+        assert_eq!(format_total(weights.total(&counts)), "61");
+        assert_eq!(format_total(0.5 * 3.0), "1.5");
+
+        assert!(matches!(
+            "mouse=1".parse::<Weights>(),
+            Err(WeightsError::UnknownKind(_))
+        ));
+        assert!(matches!(
+            "keys=-1".parse::<Weights>(),
+            Err(WeightsError::InvalidWeight(_))
+        ));
+        assert!(matches!(
+            "keys".parse::<Weights>(),
+            Err(WeightsError::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn test_partial_weights_table() {
+        let weights: Weights = toml::from_str("scrolls = 0.5").unwrap();
+        assert_eq!(weights.keys, 1.0);
+        assert_eq!(weights.scrolls, 0.5);
+    }
+}
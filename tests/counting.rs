@@ -0,0 +1,140 @@
+//! Runs wl-actions between the mock compositor and a test client and checks
+//! what ends up in the summary.
+
+mod mock;
+
+use mock::{Harness, PRESSED, RELEASED};
+
+const KEY_A: u32 = 30;
+const KEY_S: u32 = 31;
+const KEY_ESC: u32 = 1;
+const KEY_CAPSLOCK: u32 = 58;
+const BTN_LEFT: u32 = 0x110;
+
+#[test]
+fn test_key_repeats_count_once() {
+    let harness = Harness::start(&[], 1);
+    harness.compositor.key(KEY_A, PRESSED);
+    // A second press without a release, e.g. from a misbehaving compositor
+    harness.compositor.key(KEY_A, PRESSED);
+    harness.compositor.key(KEY_A, RELEASED);
+    harness.compositor.key(KEY_S, PRESSED);
+    harness.compositor.key(KEY_S, RELEASED);
+
+    let summary = harness.finish();
+    assert_eq!(summary.counters.key_presses, 2);
+}
+
+#[test]
+fn test_keyboards_are_deduplicated() {
+    // The compositor sends every key to each of the client's keyboards
+    let harness = Harness::start(&[], 2);
+    harness.compositor.key(KEY_A, PRESSED);
+    harness.compositor.key(KEY_A, RELEASED);
+
+    let summary = harness.finish();
+    assert_eq!(summary.counters.key_presses, 1);
+}
+
+#[test]
+fn test_keys_released_without_focus() {
+    let harness = Harness::start(&[], 1);
+    harness.compositor.keyboard_enter(&[]);
+    harness.compositor.key(KEY_A, PRESSED);
+    // Released while another client has focus
+    harness.compositor.keyboard_leave();
+    harness.compositor.keyboard_enter(&[KEY_S]);
+    harness.compositor.key(KEY_A, PRESSED);
+    // Went down before the focus came back
+    harness.compositor.key(KEY_S, PRESSED);
+
+    let summary = harness.finish();
+    assert_eq!(summary.counters.key_presses, 2);
+}
+
+#[test]
+fn test_filtered_keys() {
+    let mut harness = Harness::start(&["--filter", "Caps_Lock=Escape,S=drop"], 1);
+    for key in [KEY_CAPSLOCK, KEY_S, KEY_A] {
+        harness.compositor.key(key, PRESSED);
+        harness.compositor.key(key, RELEASED);
+    }
+    assert_eq!(
+        harness.client_keys(),
+        [
+            (KEY_ESC, PRESSED),
+            (KEY_ESC, RELEASED),
+            (KEY_A, PRESSED),
+            (KEY_A, RELEASED)
+        ]
+    );
+
+    let summary = harness.finish();
+    assert_eq!(summary.counters.key_presses, 2);
+    assert_eq!(summary.filtered["Caps_Lock=Escape"], 1);
+    assert_eq!(summary.filtered["S=drop"], 1);
+}
+
+#[test]
+fn test_buttons_and_touch() {
+    let harness = Harness::start(&[], 1);
+    harness.compositor.pointer_enter(10.0, 20.0);
+    harness.compositor.button(BTN_LEFT, PRESSED);
+    harness.compositor.button(BTN_LEFT, PRESSED);
+    harness.compositor.button(BTN_LEFT, RELEASED);
+    harness.compositor.button(BTN_LEFT, PRESSED);
+    harness.compositor.button(BTN_LEFT, RELEASED);
+    harness.compositor.touch_down(0, 5.0, 5.0);
+    harness.compositor.touch_down(1, 50.0, 50.0);
+
+    let summary = harness.finish();
+    assert_eq!(summary.counters.button_clicks, 2);
+    assert_eq!(summary.counters.touch_taps, 2);
+}
+
+#[test]
+fn test_scroll_bursts_are_debounced() {
+    // A window longer than the test, so every scroll falls into one burst
+    let mut harness = Harness::start(&["--scroll-debounce-ms", "600000"], 1);
+    harness.compositor.pointer_enter(0.0, 0.0);
+    // One gesture of smooth scrolling and wheel clicks
+    for _ in 0..5 {
+        harness.compositor.axis(2.5);
+    }
+    harness.compositor.wheel(1);
+    harness.sync();
+    harness.compositor.wheel(1);
+
+    let summary = harness.finish();
+    assert_eq!(summary.counters.scroll_steps, 1);
+}
+
+#[test]
+fn test_scroll_bursts_without_debounce() {
+    let harness = Harness::start(&["--scroll-debounce-ms", "0"], 1);
+    harness.compositor.pointer_enter(0.0, 0.0);
+    for _ in 0..5 {
+        harness.compositor.axis(2.5);
+    }
+
+    let summary = harness.finish();
+    assert_eq!(summary.counters.scroll_steps, 5);
+}
+
+#[test]
+fn test_replay_against_budget() {
+    let harness = Harness::start(&["--record", "recording.jsonl"], 1);
+    harness.compositor.key(KEY_A, PRESSED);
+    harness.compositor.key(KEY_A, RELEASED);
+    harness.compositor.pointer_enter(10.0, 20.0);
+    harness.compositor.button(BTN_LEFT, PRESSED);
+    harness.compositor.button(BTN_LEFT, RELEASED);
+    harness.compositor.touch_down(0, 5.0, 5.0);
+
+    let (summary, status, replayed) = harness.finish_and_replay(&["--assert", "clicks<=0"]);
+    assert_eq!(status.code(), Some(3));
+    assert_eq!(replayed.counters, summary.counters);
+    assert_eq!(replayed.keys, summary.keys);
+    assert_eq!(replayed.klm, summary.klm);
+    assert_eq!(replayed.counters.key_presses, 1);
+}
@@ -0,0 +1,534 @@
+//! A stand-in compositor and test client for running wl-actions headless.
+//!
+//! The compositor listens on a socket in a temporary runtime directory and
+//! offers `wl_compositor` and `wl_seat`. wl-actions connects to it and wraps
+//! a shell that only reports the display it was given, so that the test
+//! itself can connect to wl-actions as the client. Input events are scripted
+//! from the compositor side and the session ends through the control socket,
+//! which writes the summary as JSON.
+
+mod wire;
+
+use {
+    std::{
+        fs::{self, File},
+        io::{BufRead, BufReader, Write},
+        os::unix::net::{UnixListener, UnixStream},
+        path::{Path, PathBuf},
+        process::{Child, Command, ExitStatus, Stdio},
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicBool, AtomicU32, Ordering},
+        },
+        thread,
+        time::{Duration, Instant},
+    },
+    wire::{Arg, Connection, Message},
+    wl_actions::Summary,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Where the stderr of wl-actions goes, in the directory of the harness.
+const LOG: &str = "wl-actions.log";
+
+const WL_DISPLAY: u32 = 1;
+const WL_KEYBOARD_KEY: u16 = 3;
+const COMPOSITOR_GLOBAL: u32 = 1;
+const SEAT_GLOBAL: u32 = 2;
+/// Pointer, keyboard and touch.
+const SEAT_CAPABILITIES: u32 = 7;
+
+pub const PRESSED: u32 = 1;
+pub const RELEASED: u32 = 0;
+
+/// The proxy's objects on the compositor side, by interface.
+#[derive(Debug, Default)]
+struct Objects {
+    registries: Vec<u32>,
+    compositors: Vec<u32>,
+    seats: Vec<u32>,
+    surfaces: Vec<u32>,
+    keyboards: Vec<u32>,
+    pointers: Vec<u32>,
+    touches: Vec<u32>,
+}
+
+pub struct Compositor {
+    writer: Arc<Mutex<Connection>>,
+    objects: Arc<Mutex<Objects>>,
+    serial: AtomicU32,
+    started: Instant,
+    running: Arc<AtomicBool>,
+}
+
+impl Compositor {
+    /// Serves requests from the proxy on a background thread.
+    fn serve(stream: UnixStream) -> Self {
+        let writer = Arc::new(Mutex::new(
+            Connection::new(stream.try_clone().unwrap()).unwrap(),
+        ));
+        let objects = Arc::new(Mutex::new(Objects::default()));
+        let running = Arc::new(AtomicBool::new(true));
+        {
+            let mut reader = Connection::new(stream).unwrap();
+            let writer = writer.clone();
+            let objects = objects.clone();
+            let running = running.clone();
+            thread::spawn(move || {
+                while running.load(Ordering::Relaxed) {
+                    match reader.try_recv() {
+                        Ok(Some(request)) => {
+                            let mut objects = objects.lock().unwrap();
+                            let mut writer = writer.lock().unwrap();
+                            handle_request(&request, &mut objects, &mut writer);
+                        }
+                        Ok(None) => {}
+                        Err(_) => break,
+                    }
+                }
+            });
+        }
+        Self {
+            writer,
+            objects,
+            serial: AtomicU32::new(1),
+            started: Instant::now(),
+            running,
+        }
+    }
+
+    fn serial(&self) -> u32 {
+        self.serial.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn time(&self) -> u32 {
+        self.started.elapsed().as_millis() as u32
+    }
+
+    /// Sends an event to every object in `targets`.
+    fn broadcast(&self, targets: impl Fn(&Objects) -> &Vec<u32>, opcode: u16, args: &[Arg<'_>]) {
+        let objects = self.objects.lock().unwrap();
+        let mut writer = self.writer.lock().unwrap();
+        for &object in targets(&objects) {
+            writer.send(object, opcode, args).unwrap();
+        }
+    }
+
+    fn surface(&self) -> u32 {
+        self.objects.lock().unwrap().surfaces[0]
+    }
+
+    /// `wl_keyboard.key` on every keyboard, as the compositor would for a
+    /// client that bound several.
+    pub fn key(&self, key: u32, state: u32) {
+        let args = [
+            Arg::Uint(self.serial()),
+            Arg::Uint(self.time()),
+            Arg::Uint(key),
+            Arg::Uint(state),
+        ];
+        self.broadcast(|objects| &objects.keyboards, 3, &args);
+    }
+
+    /// `wl_keyboard.enter` on every keyboard, with the keys that are down.
+    pub fn keyboard_enter(&self, keys: &[u32]) {
+        let keys: Vec<u8> = keys.iter().flat_map(|key| key.to_ne_bytes()).collect();
+        let args = [
+            Arg::Uint(self.serial()),
+            Arg::Uint(self.surface()),
+            Arg::Array(&keys),
+        ];
+        self.broadcast(|objects| &objects.keyboards, 1, &args);
+    }
+
+    pub fn keyboard_leave(&self) {
+        let args = [Arg::Uint(self.serial()), Arg::Uint(self.surface())];
+        self.broadcast(|objects| &objects.keyboards, 2, &args);
+    }
+
+    pub fn pointer_enter(&self, x: f64, y: f64) {
+        let args = [
+            Arg::Uint(self.serial()),
+            Arg::Uint(self.surface()),
+            Arg::Fixed(x),
+            Arg::Fixed(y),
+        ];
+        self.broadcast(|objects| &objects.pointers, 0, &args);
+        self.pointer_frame();
+    }
+
+    pub fn button(&self, button: u32, state: u32) {
+        let args = [
+            Arg::Uint(self.serial()),
+            Arg::Uint(self.time()),
+            Arg::Uint(button),
+            Arg::Uint(state),
+        ];
+        self.broadcast(|objects| &objects.pointers, 3, &args);
+        self.pointer_frame();
+    }
+
+    /// A smooth vertical `wl_pointer.axis` event.
+    pub fn axis(&self, value: f64) {
+        let args = [Arg::Uint(self.time()), Arg::Uint(0), Arg::Fixed(value)];
+        self.broadcast(|objects| &objects.pointers, 4, &args);
+        self.pointer_frame();
+    }
+
+    /// A vertical wheel click: `axis_discrete` followed by `axis`, as sent to
+    /// clients before version 8.
+    pub fn wheel(&self, steps: i32) {
+        let args = [Arg::Uint(0), Arg::Int(steps)];
+        self.broadcast(|objects| &objects.pointers, 8, &args);
+        self.axis(15.0 * steps as f64);
+    }
+
+    fn pointer_frame(&self) {
+        self.broadcast(|objects| &objects.pointers, 5, &[]);
+    }
+
+    pub fn touch_down(&self, id: i32, x: f64, y: f64) {
+        let args = [
+            Arg::Uint(self.serial()),
+            Arg::Uint(self.time()),
+            Arg::Uint(self.surface()),
+            Arg::Int(id),
+            Arg::Fixed(x),
+            Arg::Fixed(y),
+        ];
+        self.broadcast(|objects| &objects.touches, 0, &args);
+        let args = [
+            Arg::Uint(self.serial()),
+            Arg::Uint(self.time()),
+            Arg::Int(id),
+        ];
+        self.broadcast(|objects| &objects.touches, 1, &args);
+        self.broadcast(|objects| &objects.touches, 3, &[]);
+    }
+}
+
+impl Drop for Compositor {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn handle_request(request: &Message, objects: &mut Objects, out: &mut Connection) {
+    let object = request.object;
+    match (object, request.opcode) {
+        // wl_display.sync
+        (WL_DISPLAY, 0) => {
+            let callback = request.word(0);
+            out.send(callback, 0, &[Arg::Uint(0)]).unwrap();
+            out.send(WL_DISPLAY, 1, &[Arg::Uint(callback)]).unwrap();
+        }
+        // wl_display.get_registry
+        (WL_DISPLAY, 1) => {
+            let registry = request.word(0);
+            objects.registries.push(registry);
+            for (name, interface, version) in [
+                (COMPOSITOR_GLOBAL, "wl_compositor", 4),
+                (SEAT_GLOBAL, "wl_seat", 7),
+            ] {
+                let args = [Arg::Uint(name), Arg::Str(interface), Arg::Uint(version)];
+                out.send(registry, 0, &args).unwrap();
+            }
+        }
+        // wl_registry.bind
+        (_, 0) if objects.registries.contains(&object) => {
+            let name = request.word(0);
+            let (_, next) = request.string(1);
+            let id = request.word(next + 1);
+            match name {
+                COMPOSITOR_GLOBAL => objects.compositors.push(id),
+                SEAT_GLOBAL => {
+                    objects.seats.push(id);
+                    out.send(id, 0, &[Arg::Uint(SEAT_CAPABILITIES)]).unwrap();
+                }
+                _ => {}
+            }
+        }
+        // wl_compositor.create_surface
+        (_, 0) if objects.compositors.contains(&object) => {
+            objects.surfaces.push(request.word(0));
+        }
+        // wl_seat.get_pointer, get_keyboard and get_touch
+        (_, opcode) if objects.seats.contains(&object) => {
+            let id = request.word(0);
+            match opcode {
+                0 => objects.pointers.push(id),
+                1 => objects.keyboards.push(id),
+                2 => objects.touches.push(id),
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The test's own Wayland client, connected to wl-actions.
+struct Client {
+    connection: Connection,
+    next_id: u32,
+    keyboards: Vec<u32>,
+}
+
+impl Client {
+    fn new_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Waits until the requests sent so far were handled, returning the
+    /// messages received meanwhile.
+    fn roundtrip(&mut self) -> Vec<Message> {
+        let callback = self.new_id();
+        self.connection
+            .send(WL_DISPLAY, 0, &[Arg::Uint(callback)])
+            .unwrap();
+        let mut received = Vec::new();
+        loop {
+            let message = self.connection.recv(TIMEOUT).unwrap();
+            if message.object == callback {
+                return received;
+            }
+            received.push(message);
+        }
+    }
+
+    /// Binds the seat and asks for `keyboards` keyboards, a pointer, touch
+    /// and one surface.
+    fn setup(&mut self, keyboards: usize) {
+        let registry = self.new_id();
+        self.connection
+            .send(WL_DISPLAY, 1, &[Arg::Uint(registry)])
+            .unwrap();
+        let globals: Vec<_> = self
+            .roundtrip()
+            .into_iter()
+            .filter(|message| message.object == registry && message.opcode == 0)
+            .map(|global| (global.word(0), global.string(1).0))
+            .collect();
+        let name_of = |interface: &str| {
+            globals
+                .iter()
+                .find(|(_, name)| name == interface)
+                .map(|&(name, _)| name)
+                .unwrap_or_else(|| panic!("no {} global", interface))
+        };
+
+        let compositor = self.bind(registry, name_of("wl_compositor"), "wl_compositor", 4);
+        let seat = self.bind(registry, name_of("wl_seat"), "wl_seat", 7);
+        let surface = self.new_id();
+        self.connection
+            .send(compositor, 0, &[Arg::Uint(surface)])
+            .unwrap();
+        let pointer = self.new_id();
+        self.connection
+            .send(seat, 0, &[Arg::Uint(pointer)])
+            .unwrap();
+        for _ in 0..keyboards {
+            let keyboard = self.new_id();
+            self.connection
+                .send(seat, 1, &[Arg::Uint(keyboard)])
+                .unwrap();
+            self.keyboards.push(keyboard);
+        }
+        let touch = self.new_id();
+        self.connection.send(seat, 2, &[Arg::Uint(touch)]).unwrap();
+        self.roundtrip();
+    }
+
+    fn bind(&mut self, registry: u32, name: u32, interface: &str, version: u32) -> u32 {
+        let id = self.new_id();
+        let args = [
+            Arg::Uint(name),
+            Arg::Str(interface),
+            Arg::Uint(version),
+            Arg::Uint(id),
+        ];
+        self.connection.send(registry, 0, &args).unwrap();
+        id
+    }
+}
+
+/// wl-actions running between the mock compositor and the test client.
+pub struct Harness {
+    pub compositor: Compositor,
+    /// The connections after the first, kept open until the harness ends.
+    others: Arc<Mutex<Vec<Compositor>>>,
+    client: Client,
+    wl_actions: Child,
+    dir: PathBuf,
+}
+
+impl Harness {
+    /// Starts wl-actions with `args` and connects a client with
+    /// `keyboards` keyboards.
+    pub fn start(args: &[&str], keyboards: usize) -> Self {
+        static SESSIONS: AtomicU32 = AtomicU32::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "wl-actions-test-{}-{}",
+            std::process::id(),
+            SESSIONS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let compositor_socket = dir.join("compositor");
+        let listener = UnixListener::bind(&compositor_socket).unwrap();
+        let wl_actions = Command::new(env!("CARGO_BIN_EXE_wl-actions"))
+            .args(["--no-config", "--quiet"])
+            .arg("--control-socket")
+            .arg(dir.join("control.sock"))
+            .arg("--summary-json")
+            .arg(dir.join("summary.json"))
+            .args(args)
+            .args([
+                "--",
+                "sh",
+                "-c",
+                r#"echo "$WAYLAND_DISPLAY" > display.tmp && mv display.tmp display && exec sleep 60"#,
+            ])
+            .current_dir(&dir)
+            .env("XDG_RUNTIME_DIR", &dir)
+            .env("WAYLAND_DISPLAY", &compositor_socket)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(File::create(dir.join(LOG)).unwrap())
+            .spawn()
+            .unwrap();
+
+        let log = dir.join(LOG);
+        let stream = accept(&listener, &log);
+        let compositor = Compositor::serve(stream);
+        // The proxy connects again for every other client, keep serving them
+        let others = Arc::new(Mutex::new(Vec::new()));
+        {
+            let others = others.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else {
+                        break;
+                    };
+                    others.lock().unwrap().push(Compositor::serve(stream));
+                }
+            });
+        }
+
+        let display = wait_for(&log, || fs::read_to_string(dir.join("display")).ok());
+        let display = Path::new(display.trim());
+        let stream = UnixStream::connect(dir.join(display)).unwrap();
+        let mut client = Client {
+            connection: Connection::new(stream).unwrap(),
+            next_id: 2,
+            keyboards: Vec::new(),
+        };
+        client.setup(keyboards);
+        wait_for(&log, || {
+            let objects = compositor.objects.lock().unwrap();
+            (objects.keyboards.len() == keyboards
+                && !objects.pointers.is_empty()
+                && !objects.touches.is_empty()
+                && !objects.surfaces.is_empty())
+            .then_some(())
+        });
+
+        Self {
+            compositor,
+            others,
+            client,
+            wl_actions,
+            dir,
+        }
+    }
+
+    /// Waits until wl-actions has handled every event sent so far.
+    pub fn sync(&mut self) {
+        self.client.roundtrip();
+    }
+
+    /// Waits until wl-actions has handled every event sent so far and
+    /// returns the keys and states the client's first keyboard received.
+    pub fn client_keys(&mut self) -> Vec<(u32, u32)> {
+        let keyboard = self.client.keyboards[0];
+        self.client
+            .roundtrip()
+            .into_iter()
+            .filter(|message| message.object == keyboard && message.opcode == WL_KEYBOARD_KEY)
+            .map(|key| (key.word(2), key.word(3)))
+            .collect()
+    }
+
+    /// Stops the session through the control socket and returns its
+    /// summary.
+    pub fn finish(mut self) -> Summary {
+        self.stop()
+    }
+
+    /// Stops the session, which was started with `--record
+    /// recording.jsonl`, and replays the recording with `args`. Returns the
+    /// summary, the status of the replay and the summary of the replay.
+    pub fn finish_and_replay(mut self, args: &[&str]) -> (Summary, ExitStatus, Summary) {
+        let summary = self.stop();
+        let status = Command::new(env!("CARGO_BIN_EXE_wl-actions"))
+            .args(["--no-config", "replay", "recording.jsonl"])
+            .args(["--summary-json", "replay.json"])
+            .args(args)
+            .current_dir(&self.dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        let json = fs::read_to_string(self.dir.join("replay.json")).unwrap();
+        (summary, status, serde_json::from_str(&json).unwrap())
+    }
+
+    fn stop(&mut self) -> Summary {
+        self.sync();
+        let mut control = UnixStream::connect(self.dir.join("control.sock")).unwrap();
+        writeln!(control, "stop").unwrap();
+        let mut response = String::new();
+        BufReader::new(&control).read_line(&mut response).unwrap();
+        assert!(response.contains(r#""ok":true"#), "{}", response);
+
+        wait_for(&self.dir.join(LOG), || self.wl_actions.try_wait().unwrap());
+        let json = fs::read_to_string(self.dir.join("summary.json")).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        self.others.lock().unwrap().clear();
+        let _ = self.wl_actions.kill();
+        let _ = self.wl_actions.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn accept(listener: &UnixListener, log: &Path) -> UnixStream {
+    listener.set_nonblocking(true).unwrap();
+    let stream = wait_for(log, || listener.accept().ok().map(|(stream, _)| stream));
+    listener.set_nonblocking(false).unwrap();
+    stream.set_nonblocking(false).unwrap();
+    stream
+}
+
+/// Polls `f` until it returns a value, panicking with the stderr of
+/// wl-actions in `log` after [`TIMEOUT`].
+fn wait_for<T>(log: &Path, mut f: impl FnMut() -> Option<T>) -> T {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if let Some(value) = f() {
+            return value;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "timed out, wl-actions wrote:\n{}",
+            fs::read_to_string(log).unwrap_or_default()
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+}
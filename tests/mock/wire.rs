@@ -0,0 +1,142 @@
+//! Just enough of the Wayland wire format for the mock compositor and the
+//! test client. File descriptors are never sent.
+
+use std::{
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    time::{Duration, Instant},
+};
+
+pub enum Arg<'a> {
+    Uint(u32),
+    Int(i32),
+    Fixed(f64),
+    Str(&'a str),
+    Array(&'a [u8]),
+}
+
+/// One decoded message, with the arguments still encoded.
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub object: u32,
+    pub opcode: u16,
+    pub body: Vec<u8>,
+}
+
+impl Message {
+    /// The `n`th 32-bit word of the arguments.
+    pub fn word(&self, n: usize) -> u32 {
+        u32::from_ne_bytes(self.body[n * 4..n * 4 + 4].try_into().unwrap())
+    }
+
+    /// A string argument starting at word `n`, and the word after it.
+    pub fn string(&self, n: usize) -> (String, usize) {
+        let len = self.word(n) as usize;
+        let start = (n + 1) * 4;
+        let text = String::from_utf8_lossy(&self.body[start..start + len.saturating_sub(1)]);
+        (text.into_owned(), n + 1 + len.div_ceil(4))
+    }
+}
+
+pub struct Connection {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    pub fn new(stream: UnixStream) -> io::Result<Self> {
+        stream.set_read_timeout(Some(Duration::from_millis(10)))?;
+        Ok(Self {
+            stream,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn send(&mut self, object: u32, opcode: u16, args: &[Arg<'_>]) -> io::Result<()> {
+        let mut body = Vec::new();
+        for arg in args {
+            match *arg {
+                Arg::Uint(value) => body.extend_from_slice(&value.to_ne_bytes()),
+                Arg::Int(value) => body.extend_from_slice(&value.to_ne_bytes()),
+                Arg::Fixed(value) => {
+                    body.extend_from_slice(&((value * 256.0).round() as i32).to_ne_bytes())
+                }
+                Arg::Str(value) => {
+                    body.extend_from_slice(&(value.len() as u32 + 1).to_ne_bytes());
+                    body.extend_from_slice(value.as_bytes());
+                    body.push(0);
+                    pad(&mut body);
+                }
+                Arg::Array(value) => {
+                    body.extend_from_slice(&(value.len() as u32).to_ne_bytes());
+                    body.extend_from_slice(value);
+                    pad(&mut body);
+                }
+            }
+        }
+        let size = (body.len() + 8) as u32;
+        let mut message = Vec::with_capacity(size as usize);
+        message.extend_from_slice(&object.to_ne_bytes());
+        message.extend_from_slice(&((size << 16) | opcode as u32).to_ne_bytes());
+        message.extend_from_slice(&body);
+        self.stream.write_all(&message)
+    }
+
+    /// The next message, or `None` if nothing arrived for a little while.
+    pub fn try_recv(&mut self) -> io::Result<Option<Message>> {
+        loop {
+            if let Some(message) = self.take_message() {
+                return Ok(Some(message));
+            }
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(None);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for the next message.
+    pub fn recv(&mut self, timeout: Duration) -> io::Result<Message> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if let Some(message) = self.try_recv()? {
+                return Ok(message);
+            }
+        }
+        Err(io::ErrorKind::TimedOut.into())
+    }
+
+    fn take_message(&mut self) -> Option<Message> {
+        if self.buffer.len() < 8 {
+            return None;
+        }
+        let header = u32::from_ne_bytes(self.buffer[4..8].try_into().unwrap());
+        let size = (header >> 16) as usize;
+        if self.buffer.len() < size {
+            return None;
+        }
+        let message = Message {
+            object: u32::from_ne_bytes(self.buffer[..4].try_into().unwrap()),
+            opcode: header as u16,
+            body: self.buffer[8..size].to_vec(),
+        };
+        self.buffer.drain(..size);
+        Some(message)
+    }
+}
+
+fn pad(body: &mut Vec<u8>) {
+    while !body.len().is_multiple_of(4) {
+        body.push(0);
+    }
+}